@@ -0,0 +1,748 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::instruction::Instruction;
+use super::memory::PROGRAM_START;
+
+/// Maximum depth of nested `:include` directives.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Size of the largest memory (XO-CHIP); programs for other platforms should stay below 0x1000.
+const MEMORY_SIZE: usize = 0x10000;
+
+/// Assemble an Octo-flavoured source file into a ROM.
+///
+/// See `assemble` for the supported syntax; `:include` paths are relative to the including file.
+pub fn assemble_file(path: &str) -> Result<Vec<u8>, String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut assembler = Assembler::new();
+    assembler.source(&source, Path::new(path), 0)?;
+    assembler.finish()
+}
+
+/// Assemble an Octo-flavoured source into a ROM, to be loaded at 0x200.
+///
+/// The following statements are supported:
+/// - `: name` defines a label, and a bare `name` calls it;
+/// - `:const NAME value` and `:alias NAME vX` define constants and register aliases;
+/// - `:byte value` or a bare number emit a data byte, and `:org address` moves the output;
+/// - `:sprite ..XX..XX .XXXXXX.` emits one byte (or two for 16 characters rows) per row;
+/// - `:include "file"` assembles another file in place;
+/// - Octo instructions, e.g. `v1 := 0x20`, `i := label`, `sprite v0 v1 5`, `jump label`, ...;
+/// - SUPER-CHIP instructions: `hires`, `lores`, `scroll-down n`, `scroll-left`, `scroll-right`,
+///   `exit`, `i := bighex vX`, `saveflags vX`, `loadflags vX` and `sprite vX vY 0` for 16x16
+///   sprites;
+/// - XO-CHIP instructions: `save vX - vY`, `load vX - vY`, `i := long NNNN`, `plane n`, `audio`
+///   and `pitch := vX`;
+/// - `if ... then`, `if ... begin ... else ... end`, `loop ... while ... again`.
+///
+/// Comments start with `#`. Errors report the file and line where they occurred.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    let mut assembler = Assembler::new();
+    assembler.source(source, Path::new("<source>"), 0)?;
+    assembler.finish()
+}
+
+/// A token with its location, for error reporting.
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    file: PathBuf,
+    line: usize,
+}
+
+impl Token {
+    fn error(&self, msg: &str) -> String {
+        format!("{}:{}: {}", self.file.display(), self.line, msg)
+    }
+}
+
+/// A reference to a label that was not defined yet.
+struct Fixup {
+    offset: usize,
+    token: Token,
+    /// The address is the 16 bits word after the opcode (`i := long`), instead of 12 bits.
+    long: bool,
+}
+
+/// An open control structure.
+enum Block {
+    /// `if ... begin`, with the offset of the jump to patch at `else`/`end`.
+    If { jump: usize, has_else: bool },
+    /// `loop`, with its start address and the offsets of the `while` exit jumps.
+    Loop { start: u16, exits: Vec<usize> },
+}
+
+/// Condition of an `if` or a `while`.
+#[derive(Clone, Copy)]
+enum Condition {
+    EqImm(u8, u8),
+    NeImm(u8, u8),
+    EqReg(u8, u8),
+    NeReg(u8, u8),
+    Key(u8),
+    NotKey(u8),
+}
+
+impl Condition {
+    /// Instruction skipping the next one when the condition is true.
+    fn skip_if_true(self) -> Instruction {
+        match self {
+            Condition::EqImm(x, nn) => Instruction::SkipEqImm { x, nn },
+            Condition::NeImm(x, nn) => Instruction::SkipNeImm { x, nn },
+            Condition::EqReg(x, y) => Instruction::SkipEqReg { x, y },
+            Condition::NeReg(x, y) => Instruction::SkipNeReg { x, y },
+            Condition::Key(x) => Instruction::SkipKey { x },
+            Condition::NotKey(x) => Instruction::SkipNotKey { x },
+        }
+    }
+
+    /// Instruction skipping the next one when the condition is false.
+    fn skip_if_false(self) -> Instruction {
+        match self {
+            Condition::EqImm(x, nn) => Condition::NeImm(x, nn).skip_if_true(),
+            Condition::NeImm(x, nn) => Condition::EqImm(x, nn).skip_if_true(),
+            Condition::EqReg(x, y) => Condition::NeReg(x, y).skip_if_true(),
+            Condition::NeReg(x, y) => Condition::EqReg(x, y).skip_if_true(),
+            Condition::Key(x) => Condition::NotKey(x).skip_if_true(),
+            Condition::NotKey(x) => Condition::Key(x).skip_if_true(),
+        }
+    }
+}
+
+struct Assembler {
+    output: Vec<u8>,
+    position: usize,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, u8>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<(Block, Token)>,
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        Assembler {
+            output: Vec::new(),
+            position: 0,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Assemble the contents of a file.
+    fn source(&mut self, source: &str, file: &Path, depth: usize) -> Result<(), String> {
+        let tokens = tokenize(source, file)?;
+        let mut cursor = Cursor { tokens, next: 0 };
+        while let Some(token) = cursor.next() {
+            self.statement(&token, &mut cursor, depth)?;
+        }
+        Ok(())
+    }
+
+    /// Resolve the pending labels and return the ROM.
+    fn finish(mut self) -> Result<Vec<u8>, String> {
+        if let Some((_, token)) = self.blocks.last() {
+            return Err(token.error(&format!("`{}` is never closed", token.text)));
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let token = &fixup.token;
+            let address = *self
+                .labels
+                .get(&token.text)
+                .ok_or_else(|| token.error(&format!("undefined label `{}`", token.text)))?;
+            if fixup.long {
+                self.output[fixup.offset + 2..fixup.offset + 4]
+                    .copy_from_slice(&address.to_be_bytes());
+            } else {
+                self.patch(token, fixup.offset, address)?;
+            }
+        }
+        Ok(self.output)
+    }
+
+    fn here(&self) -> u16 {
+        (PROGRAM_START + self.position) as u16
+    }
+
+    fn emit_byte(&mut self, token: &Token, byte: u8) -> Result<(), String> {
+        if PROGRAM_START + self.position >= MEMORY_SIZE {
+            return Err(token.error("program does not fit in memory"));
+        }
+        if self.position >= self.output.len() {
+            self.output.resize(self.position + 1, 0);
+        }
+        self.output[self.position] = byte;
+        self.position += 1;
+        Ok(())
+    }
+
+    fn emit(&mut self, token: &Token, instruction: Instruction) -> Result<usize, String> {
+        let offset = self.position;
+        for byte in instruction.to_bytes() {
+            self.emit_byte(token, byte)?;
+        }
+        Ok(offset)
+    }
+
+    /// Emit an instruction with a 12 bits address, possibly referring to a label defined later.
+    fn emit_address(
+        &mut self,
+        token: &Token,
+        target: &Token,
+        instruction: fn(u16) -> Instruction,
+    ) -> Result<(), String> {
+        match self.address(target)? {
+            Some(nnn) if nnn > 0xFFF => {
+                return Err(target.error(&format!("address {:#X} out of range", nnn)))
+            }
+            Some(nnn) => {
+                self.emit(token, instruction(nnn))?;
+            }
+            None => {
+                let offset = self.emit(token, instruction(0))?;
+                self.fixups.push(Fixup {
+                    offset,
+                    token: target.clone(),
+                    long: false,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Patch the address of an already emitted jump.
+    fn patch(&mut self, token: &Token, offset: usize, address: u16) -> Result<(), String> {
+        if address > 0xFFF {
+            return Err(token.error(&format!("address {:#X} out of range", address)));
+        }
+        self.output[offset] = (self.output[offset] & 0xF0) | ((address >> 8) & 0x0F) as u8;
+        self.output[offset + 1] = (address & 0xFF) as u8;
+        Ok(())
+    }
+
+    fn statement(
+        &mut self,
+        token: &Token,
+        cursor: &mut Cursor,
+        depth: usize,
+    ) -> Result<(), String> {
+        match token.text.as_str() {
+            ":" => {
+                let name = cursor.expect(token)?;
+                self.check_name(&name)?;
+                if self.labels.insert(name.text.clone(), self.here()).is_some() {
+                    return Err(name.error(&format!("label `{}` defined twice", name.text)));
+                }
+            }
+            ":const" => {
+                let name = cursor.expect(token)?;
+                self.check_name(&name)?;
+                let value = cursor.expect(token)?;
+                let value = self.number(&value)?;
+                self.constants.insert(name.text, value);
+            }
+            ":alias" => {
+                let name = cursor.expect(token)?;
+                self.check_name(&name)?;
+                let register = cursor.expect(token)?;
+                let register = self.register(&register)?;
+                self.aliases.insert(name.text, register);
+            }
+            ":byte" => {
+                let value = cursor.expect(token)?;
+                let byte = self.byte(&value)?;
+                self.emit_byte(&value, byte)?;
+            }
+            ":org" => {
+                let value = cursor.expect(token)?;
+                let address = self.number(&value)?;
+                if !(PROGRAM_START as i64..MEMORY_SIZE as i64).contains(&address) {
+                    return Err(value.error("origin must be between 0x200 and 0xFFFF"));
+                }
+                self.position = address as usize - PROGRAM_START;
+            }
+            ":sprite" => {
+                let mut rows = 0;
+                while let Some(row) = cursor.peek().filter(|t| is_sprite_row(&t.text)) {
+                    let row = row.clone();
+                    cursor.next();
+                    let bits = row
+                        .text
+                        .chars()
+                        .fold(0u16, |acc, c| (acc << 1) | (c == 'X') as u16);
+                    if row.text.len() == 16 {
+                        self.emit_byte(&row, (bits >> 8) as u8)?;
+                    }
+                    self.emit_byte(&row, (bits & 0xFF) as u8)?;
+                    rows += 1;
+                }
+                if rows == 0 {
+                    return Err(token.error("expected sprite rows of 8 or 16 `.` and `X`"));
+                }
+            }
+            ":include" => {
+                let path = cursor.expect(token)?;
+                self.include(&path, depth)?;
+            }
+            ":call" => {
+                let target = cursor.expect(token)?;
+                self.emit_address(token, &target, |nnn| Instruction::Call { nnn })?;
+            }
+            "clear" => {
+                self.emit(token, Instruction::Clear)?;
+            }
+            "return" | ";" => {
+                self.emit(token, Instruction::Return)?;
+            }
+            "scroll-down" => {
+                let n = cursor.expect(token)?;
+                let n = self.nibble(&n)?;
+                self.emit(token, Instruction::ScrollDown { n })?;
+            }
+            "scroll-right" => {
+                self.emit(token, Instruction::ScrollRight)?;
+            }
+            "scroll-left" => {
+                self.emit(token, Instruction::ScrollLeft)?;
+            }
+            "exit" => {
+                self.emit(token, Instruction::Exit)?;
+            }
+            "lores" => {
+                self.emit(token, Instruction::LowRes)?;
+            }
+            "hires" => {
+                self.emit(token, Instruction::HighRes)?;
+            }
+            "jump" => {
+                let target = cursor.expect(token)?;
+                self.emit_address(token, &target, |nnn| Instruction::Jump { nnn })?;
+            }
+            "jump0" => {
+                let target = cursor.expect(token)?;
+                self.emit_address(token, &target, |nnn| Instruction::JumpOffset { nnn })?;
+            }
+            "sprite" => {
+                let x = self.register(&cursor.expect(token)?)?;
+                let y = self.register(&cursor.expect(token)?)?;
+                let n = cursor.expect(token)?;
+                let n = self.nibble(&n)?;
+                self.emit(token, Instruction::Draw { x, y, n })?;
+            }
+            "bcd" => {
+                let x = self.register(&cursor.expect(token)?)?;
+                self.emit(token, Instruction::Bcd { x })?;
+            }
+            "save" | "load" => {
+                let x = self.register(&cursor.expect(token)?)?;
+                let range = match cursor.peek() {
+                    Some(dash) if dash.text == "-" => {
+                        cursor.next();
+                        Some(self.register(&cursor.expect(token)?)?)
+                    }
+                    _ => None,
+                };
+                let instruction = match (token.text.as_str(), range) {
+                    ("save", Some(y)) => Instruction::StoreRange { x, y },
+                    ("save", None) => Instruction::Store { x },
+                    (_, Some(y)) => Instruction::LoadRange { x, y },
+                    (_, None) => Instruction::Load { x },
+                };
+                self.emit(token, instruction)?;
+            }
+            "saveflags" => {
+                let x = self.register(&cursor.expect(token)?)?;
+                self.emit(token, Instruction::SaveFlags { x })?;
+            }
+            "loadflags" => {
+                let x = self.register(&cursor.expect(token)?)?;
+                self.emit(token, Instruction::LoadFlags { x })?;
+            }
+            "plane" => {
+                let n = cursor.expect(token)?;
+                let n = self.nibble(&n)?;
+                if n > 3 {
+                    return Err(token.error("plane must be between 0 and 3"));
+                }
+                self.emit(token, Instruction::Plane { n })?;
+            }
+            "audio" => {
+                self.emit(token, Instruction::Audio)?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                let op = cursor.expect(token)?;
+                if op.text != ":=" {
+                    return Err(op.error("expected `:=`"));
+                }
+                let x = self.register(&cursor.expect(token)?)?;
+                let instruction = match token.text.as_str() {
+                    "delay" => Instruction::SetDelay { x },
+                    "buzzer" => Instruction::SetSound { x },
+                    _ => Instruction::Pitch { x },
+                };
+                self.emit(token, instruction)?;
+            }
+            "i" => self.assign_i(token, cursor)?,
+            "if" => self.conditional(token, cursor)?,
+            "else" => match self.blocks.pop() {
+                Some((
+                    Block::If {
+                        jump,
+                        has_else: false,
+                    },
+                    start,
+                )) => {
+                    let end = self.emit(token, Instruction::Jump { nnn: 0 })?;
+                    self.patch(token, jump, self.here())?;
+                    self.blocks.push((
+                        Block::If {
+                            jump: end,
+                            has_else: true,
+                        },
+                        start,
+                    ));
+                }
+                _ => return Err(token.error("`else` without `if ... begin`")),
+            },
+            "end" => match self.blocks.pop() {
+                Some((Block::If { jump, .. }, _)) => self.patch(token, jump, self.here())?,
+                _ => return Err(token.error("`end` without `if ... begin`")),
+            },
+            "loop" => {
+                self.blocks.push((
+                    Block::Loop {
+                        start: self.here(),
+                        exits: Vec::new(),
+                    },
+                    token.clone(),
+                ));
+            }
+            "while" => {
+                let condition = self.condition(token, cursor)?;
+                self.emit(token, condition.skip_if_true())?;
+                let exit = self.emit(token, Instruction::Jump { nnn: 0 })?;
+                match self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find_map(|(block, _)| match block {
+                        Block::Loop { exits, .. } => Some(exits),
+                        _ => None,
+                    }) {
+                    Some(exits) => exits.push(exit),
+                    None => return Err(token.error("`while` outside of `loop`")),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some((Block::Loop { start, exits }, _)) => {
+                    self.emit(token, Instruction::Jump { nnn: start })?;
+                    for exit in exits {
+                        self.patch(token, exit, self.here())?;
+                    }
+                }
+                _ => return Err(token.error("`again` without `loop`")),
+            },
+            _ => {
+                if let Some(x) = self.try_register(&token.text) {
+                    self.assign_register(token, x, cursor)?;
+                } else if let Ok(byte) = self.byte(token) {
+                    self.emit_byte(token, byte)?;
+                } else if is_name(&token.text) && !self.constants.contains_key(&token.text) {
+                    // calling a label
+                    self.emit_address(token, token, |nnn| Instruction::Call { nnn })?;
+                } else {
+                    return Err(token.error(&format!("unexpected `{}`", token.text)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn include(&mut self, path: &Token, depth: usize) -> Result<(), String> {
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(path.error("too many nested includes"));
+        }
+        let name = path
+            .text
+            .strip_prefix('"')
+            .and_then(|p| p.strip_suffix('"'))
+            .ok_or_else(|| path.error("expected a quoted file name"))?;
+        let file = path.file.parent().unwrap_or(Path::new("")).join(name);
+        let source = fs::read_to_string(&file)
+            .map_err(|e| path.error(&format!("cannot include {}: {}", file.display(), e)))?;
+        self.source(&source, &file, depth + 1)
+    }
+
+    /// `i := NNN`, `i := long NNNN`, `i := hex vX` and `i += vX`.
+    fn assign_i(&mut self, token: &Token, cursor: &mut Cursor) -> Result<(), String> {
+        let op = cursor.expect(token)?;
+        let value = cursor.expect(token)?;
+        match op.text.as_str() {
+            ":=" if value.text == "hex" => {
+                let x = self.register(&cursor.expect(token)?)?;
+                self.emit(token, Instruction::Font { x })?;
+            }
+            ":=" if value.text == "bighex" => {
+                let x = self.register(&cursor.expect(token)?)?;
+                self.emit(token, Instruction::BigFont { x })?;
+            }
+            ":=" if value.text == "long" => {
+                let target = cursor.expect(token)?;
+                match self.address(&target)? {
+                    Some(nnnn) => {
+                        self.emit(token, Instruction::LoadILong { nnnn })?;
+                    }
+                    None => {
+                        let offset = self.emit(token, Instruction::LoadILong { nnnn: 0 })?;
+                        self.fixups.push(Fixup {
+                            offset,
+                            token: target,
+                            long: true,
+                        });
+                    }
+                }
+            }
+            ":=" => self.emit_address(token, &value, |nnn| Instruction::LoadI { nnn })?,
+            "+=" => {
+                let x = self.register(&value)?;
+                self.emit(token, Instruction::AddI { x })?;
+            }
+            _ => return Err(op.error("expected `:=` or `+=`")),
+        }
+        Ok(())
+    }
+
+    /// `vX op value`.
+    fn assign_register(&mut self, token: &Token, x: u8, cursor: &mut Cursor) -> Result<(), String> {
+        let op = cursor.expect(token)?;
+        let value = cursor.expect(token)?;
+        let y = self.try_register(&value.text);
+        let instruction = match (op.text.as_str(), y) {
+            (":=", Some(y)) => Instruction::Move { x, y },
+            (":=", None) => match value.text.as_str() {
+                "random" => {
+                    let mask = cursor.expect(token)?;
+                    Instruction::Random {
+                        x,
+                        nn: self.byte(&mask)?,
+                    }
+                }
+                "delay" => Instruction::GetDelay { x },
+                "key" => Instruction::WaitKey { x },
+                _ => Instruction::LoadImm {
+                    x,
+                    nn: self.byte(&value)?,
+                },
+            },
+            ("+=", Some(y)) => Instruction::Add { x, y },
+            ("+=", None) => Instruction::AddImm {
+                x,
+                nn: self.byte(&value)?,
+            },
+            ("-=", Some(y)) => Instruction::Sub { x, y },
+            ("=-", Some(y)) => Instruction::SubReversed { x, y },
+            ("|=", Some(y)) => Instruction::Or { x, y },
+            ("&=", Some(y)) => Instruction::And { x, y },
+            ("^=", Some(y)) => Instruction::Xor { x, y },
+            (">>=", Some(y)) => Instruction::ShiftRight { x, y },
+            ("<<=", Some(y)) => Instruction::ShiftLeft { x, y },
+            (_, None) if is_operator(&op.text) => {
+                return Err(value.error(&format!("expected a register after `{}`", op.text)))
+            }
+            _ => return Err(op.error(&format!("unknown operator `{}`", op.text))),
+        };
+        self.emit(token, instruction)?;
+        Ok(())
+    }
+
+    /// `if condition then` or `if condition begin`.
+    fn conditional(&mut self, token: &Token, cursor: &mut Cursor) -> Result<(), String> {
+        let condition = self.condition(token, cursor)?;
+        let keyword = cursor.expect(token)?;
+        match keyword.text.as_str() {
+            "then" => {
+                self.emit(token, condition.skip_if_false())?;
+            }
+            "begin" => {
+                self.emit(token, condition.skip_if_true())?;
+                let jump = self.emit(token, Instruction::Jump { nnn: 0 })?;
+                self.blocks.push((
+                    Block::If {
+                        jump,
+                        has_else: false,
+                    },
+                    token.clone(),
+                ));
+            }
+            _ => return Err(keyword.error("expected `then` or `begin`")),
+        }
+        Ok(())
+    }
+
+    /// `vX == value`, `vX != value`, `vX key` or `vX -key`.
+    fn condition(&mut self, token: &Token, cursor: &mut Cursor) -> Result<Condition, String> {
+        let x = self.register(&cursor.expect(token)?)?;
+        let op = cursor.expect(token)?;
+        match op.text.as_str() {
+            "key" => return Ok(Condition::Key(x)),
+            "-key" => return Ok(Condition::NotKey(x)),
+            "==" | "!=" => (),
+            _ => return Err(op.error("expected `==`, `!=`, `key` or `-key`")),
+        }
+        let value = cursor.expect(token)?;
+        let condition = match (op.text == "==", self.try_register(&value.text)) {
+            (true, Some(y)) => Condition::EqReg(x, y),
+            (false, Some(y)) => Condition::NeReg(x, y),
+            (true, None) => Condition::EqImm(x, self.byte(&value)?),
+            (false, None) => Condition::NeImm(x, self.byte(&value)?),
+        };
+        Ok(condition)
+    }
+
+    fn check_name(&self, token: &Token) -> Result<(), String> {
+        if !is_name(&token.text) || self.try_register(&token.text).is_some() {
+            return Err(token.error(&format!("invalid name `{}`", token.text)));
+        }
+        Ok(())
+    }
+
+    fn try_register(&self, text: &str) -> Option<u8> {
+        if let Some(&register) = self.aliases.get(text) {
+            return Some(register);
+        }
+        let digit = text.strip_prefix('v').or_else(|| text.strip_prefix('V'))?;
+        if digit.len() != 1 {
+            return None;
+        }
+        u8::from_str_radix(digit, 16).ok()
+    }
+
+    fn register(&self, token: &Token) -> Result<u8, String> {
+        self.try_register(&token.text)
+            .ok_or_else(|| token.error(&format!("expected a register, found `{}`", token.text)))
+    }
+
+    fn number(&self, token: &Token) -> Result<i64, String> {
+        if let Some(&value) = self.constants.get(&token.text) {
+            return Ok(value);
+        }
+        let (negative, text) = match token.text.strip_prefix('-') {
+            Some(text) => (true, text),
+            None => (false, token.text.as_str()),
+        };
+        let value = if let Some(hex) = text.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16)
+        } else if let Some(bin) = text.strip_prefix("0b") {
+            i64::from_str_radix(bin, 2)
+        } else {
+            text.parse::<i64>()
+        }
+        .map_err(|_| token.error(&format!("expected a number, found `{}`", token.text)))?;
+        Ok(if negative { -value } else { value })
+    }
+
+    fn byte(&self, token: &Token) -> Result<u8, String> {
+        let value = self.number(token)?;
+        if !(-128..=255).contains(&value) {
+            return Err(token.error(&format!("value {} does not fit in a byte", value)));
+        }
+        Ok(value as u8)
+    }
+
+    fn nibble(&self, token: &Token) -> Result<u8, String> {
+        let value = self.number(token)?;
+        if !(0..=15).contains(&value) {
+            return Err(token.error(&format!("value {} does not fit in 4 bits", value)));
+        }
+        Ok(value as u8)
+    }
+
+    /// Resolve an address: a number, a constant or a label (`None` if not defined yet).
+    fn address(&self, token: &Token) -> Result<Option<u16>, String> {
+        if let Some(&address) = self.labels.get(&token.text) {
+            return Ok(Some(address));
+        }
+        match self.number(token) {
+            Ok(value) if (0..=0xFFFF).contains(&value) => Ok(Some(value as u16)),
+            Ok(value) => Err(token.error(&format!("address {:#X} out of range", value))),
+            Err(_) if is_name(&token.text) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Sequence of tokens being assembled.
+struct Cursor {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Cursor {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    /// Get the next token, which is required by the statement starting at `start`.
+    fn expect(&mut self, start: &Token) -> Result<Token, String> {
+        self.next()
+            .ok_or_else(|| start.error(&format!("unexpected end of file after `{}`", start.text)))
+    }
+}
+
+fn tokenize(source: &str, file: &Path) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    for (n, line) in source.lines().enumerate() {
+        let mut rest = line;
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() || rest.starts_with('#') {
+                break;
+            }
+            let len = if let Some(quoted) = rest.strip_prefix('"') {
+                match quoted.find('"') {
+                    Some(end) => end + 2,
+                    None => {
+                        return Err(format!("{}:{}: unterminated string", file.display(), n + 1))
+                    }
+                }
+            } else {
+                rest.find(char::is_whitespace).unwrap_or(rest.len())
+            };
+            tokens.push(Token {
+                text: rest[..len].to_string(),
+                file: file.to_path_buf(),
+                line: n + 1,
+            });
+            rest = &rest[len..];
+        }
+    }
+    Ok(tokens)
+}
+
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn is_operator(text: &str) -> bool {
+    matches!(
+        text,
+        ":=" | "+=" | "-=" | "=-" | "|=" | "&=" | "^=" | ">>=" | "<<="
+    )
+}
+
+fn is_sprite_row(text: &str) -> bool {
+    (text.len() == 8 || text.len() == 16) && text.chars().all(|c| c == '.' || c == 'X')
+}
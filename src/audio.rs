@@ -1,19 +1,17 @@
 use sdl2::audio::{AudioQueue, AudioSpecDesired};
 use sdl2::Sdl;
 
-use super::commons::CanTick;
-
 /// Audio manager
 pub struct Audio {
-    time: u8,
-    ticks: u32,
-    freq: u32,
+    playing: bool,
+    phase: f64,
+    pattern: Option<([u8; 16], u8)>,
     device: AudioQueue<i16>,
 }
 
 impl Audio {
     /// Create a new audio managaer from an SDL context.
-    pub fn new(sdl: &Sdl, freq: u32) -> Result<Audio, String> {
+    pub fn new(sdl: &Sdl) -> Result<Audio, String> {
         let subsystem = sdl.audio()?;
         let spec = AudioSpecDesired {
             freq: Some(48_000),
@@ -22,56 +20,71 @@ impl Audio {
         };
         let device = subsystem.open_queue::<i16, _>(None, &spec)?;
         Ok(Audio {
-            time: 0,
-            ticks: 0,
-            freq,
+            playing: false,
+            phase: 0.0,
+            pattern: None,
             device,
         })
     }
 
-    /// Play a beeping sound for a given duration (at 60Hz).
-    pub fn play_sound(&mut self, duration: u8) {
-        // create square wave
-        let tone_volume = 1_000i16;
-        let period: usize = (self.device.spec().freq / 256).try_into().unwrap();
-        // create double the amount of samples to account for delays
-        let sample_count: usize =
-            (2 * self.device.spec().freq * self.device.spec().channels as i32 * duration as i32
-                / 60)
-                .try_into()
-                .unwrap();
-        let mut sound_to_play = vec![0; sample_count];
-        for x in 0..sample_count {
-            sound_to_play[x] = if (x / period) % 2 == 0 {
-                tone_volume
-            } else {
-                -tone_volume
-            };
+    /// Play a 128 bits audio pattern at the given pitch (XO-CHIP) instead of the default beep.
+    ///
+    /// The pattern is played at `4000 * 2 ^ ((pitch - 64) / 48)` bits per second.
+    pub fn set_pattern(&mut self, pattern: Option<[u8; 16]>, pitch: u8) {
+        self.pattern = pattern.map(|p| (p, pitch));
+    }
+
+    /// Start or stop the beeping sound; call it at least once per frame (at 60Hz).
+    pub fn set_active(&mut self, active: bool) {
+        if !active {
+            if self.playing {
+                self.device.pause();
+                self.device.clear();
+                self.playing = false;
+            }
+            return;
         }
 
-        // play
-        self.device.pause();
-        self.device.clear();
+        // keep two frames of samples queued to account for delays
+        let spec = self.device.spec();
+        let frame_samples = (spec.freq * spec.channels as i32 / 60) as usize;
+        let queued_samples = self.device.size() as usize / std::mem::size_of::<i16>();
+        if queued_samples < 2 * frame_samples {
+            self.queue_samples(2 * frame_samples - queued_samples);
+        }
+        if !self.playing {
+            self.device.resume();
+            self.playing = true;
+        }
+    }
+
+    fn queue_samples(&mut self, sample_count: usize) {
+        let tone_volume = 1_000i16;
+        let spec = self.device.spec();
+        // the phase is the position in the pattern, in bits
+        let (pattern, rate) = match self.pattern {
+            Some((pattern, pitch)) => (pattern, 4000.0 * 2f64.powf((pitch as f64 - 64.0) / 48.0)),
+            // default beep: a 256Hz square wave
+            None => (
+                [0xFF, 0xFF, 0x00, 0x00].repeat(4).try_into().unwrap(),
+                256.0 * 32.0,
+            ),
+        };
+        let step = rate / spec.freq as f64;
+        let mut sound_to_play = Vec::with_capacity(sample_count);
+        for _ in 0..sample_count / spec.channels as usize {
+            let bit = self.phase as usize;
+            let on = pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+            let sample = if on { tone_volume } else { -tone_volume };
+            for _ in 0..spec.channels {
+                sound_to_play.push(sample);
+            }
+            self.phase = (self.phase + step) % 128.0;
+        }
         self.device
             .queue_audio(&sound_to_play)
             .unwrap_or_else(|err| {
                 eprintln!("Cannot play audio: {}", err);
             });
-        self.device.resume();
-        self.time = duration;
-        self.ticks = duration as u32 * self.freq / 60;
-    }
-}
-
-/// Each tick, the audio manager check if the audio timer is expired and possibly stops the playback.
-impl CanTick for Audio {
-    fn tick(&mut self) {
-        if self.time > 0 {
-            self.ticks -= 1;
-            self.time = (self.ticks * 60 / self.freq) as u8;
-        }
-        if self.time == 0 {
-            self.device.pause();
-        }
     }
 }
@@ -1,22 +1,80 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
 use std::process;
-use std::time::{Duration, Instant};
+use std::str::FromStr;
+#[cfg(feature = "sdl")]
+use std::sync::mpsc;
+#[cfg(feature = "sdl")]
+use std::thread;
+#[cfg(feature = "terminal")]
+use std::time::Duration;
 
-use clap::Parser;
-use rand::Rng;
+use clap::{Args, Parser, Subcommand};
 
+#[cfg(feature = "sdl")]
 extern crate sdl2;
 
 extern crate rchip8;
+use rchip8::asm;
+#[cfg(feature = "sdl")]
 use rchip8::audio::Audio;
+use rchip8::chip8::Chip8;
+#[cfg(any(feature = "sdl", feature = "terminal"))]
+use rchip8::chip8::KeyInput;
+#[cfg(any(feature = "sdl", feature = "terminal"))]
 use rchip8::commons::CanTick;
+use rchip8::debugger::Debugger;
+use rchip8::disasm::{self, Syntax};
+#[cfg(feature = "sdl")]
 use rchip8::display::Display;
-use rchip8::keyboard::Keyboard;
+use rchip8::flags;
+use rchip8::gdb::GdbStub;
+use rchip8::headless::Headless;
+#[cfg(feature = "sdl")]
+use rchip8::keyboard::{Hotkey, Keyboard};
 use rchip8::memory;
-use rchip8::timer::Timer;
+use rchip8::movie::Movie;
+use rchip8::platform::Platform;
+use rchip8::quirks::Quirks;
+#[cfg(feature = "sdl")]
+use rchip8::rewind::Rewind;
+use rchip8::rng;
+#[cfg(any(feature = "sdl", feature = "terminal"))]
+use rchip8::scheduler::Scheduler;
+use rchip8::state;
+use rchip8::timing::Timing;
+use rchip8::trace::{self, Trace};
+#[cfg(feature = "terminal")]
+use rchip8::tty::Tty;
+#[cfg(feature = "terminal")]
+use rchip8::tui::Tui;
+use rchip8::vip::Vip;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-struct Args {
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    run: Option<RunArgs>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Execute a ROM or an assembly source (.8o)
+    Run(Box<RunArgs>),
+    /// Disassemble a ROM
+    Disasm(DisasmArgs),
+    /// Assemble a source file into a ROM
+    Asm(AsmArgs),
+}
+
+#[derive(Args, Debug)]
+struct RunArgs {
     /// emulated CPU frequency
     #[arg(short, long, default_value_t = 500)]
     freq: u32,
@@ -25,348 +83,881 @@ struct Args {
     #[arg(short, long, default_value_t = 10)]
     pixel: u32,
 
-    /// ROM to execute
+    /// seconds of gameplay kept to rewind with Backspace (0 to disable)
+    #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+    rewind: u32,
+
+    /// emulated platform (chip8, schip or xochip)
+    #[arg(long, default_value = "chip8")]
+    platform: Platform,
+
+    /// quirks preset (vip, chip48, schip or xochip), defaults to the platform ones
+    #[arg(long, value_name = "PRESET")]
+    quirks: Option<Quirks>,
+
+    /// override a single quirk: shift=vx|vy, memory=unchanged|x|x+1, jump=v0|vx, vf-reset=on|off,
+    /// wrap=on|off, vblank=on|off, key-wait=release|down, collision=any|rows,
+    /// lores-dxy0=16x16|8x16
+    #[arg(long, value_name = "NAME=VALUE")]
+    quirk: Vec<String>,
+
+    /// timing model: fixed (freq instructions per second) or vip (COSMAC VIP instruction costs,
+    /// chip8 platform only)
+    #[arg(long, default_value = "fixed")]
+    timing: Timing,
+
+    /// run on an emulated COSMAC VIP, executing the original CHIP-8 interpreter on its CPU
+    /// (the images of the monitor ROM and of the interpreter are not included)
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["MONITOR", "INTERPRETER"],
+        conflicts_with_all = [
+            "platform", "quirks", "quirk", "timing", "freq", "rng", "seed", "load_state",
+            "save_state", "record", "replay", "debug", "tui", "gdb", "trace",
+        ],
+    )]
+    vip: Option<Vec<String>>,
+
+    /// frontend showing the screen and reading the keys (sdl, or tty to play in a terminal)
+    #[arg(long, default_value = "sdl")]
+    frontend: Frontend,
+
+    /// milliseconds a key stays down after a press in the terminal, which does not report
+    /// key releases
+    #[arg(long, value_name = "MS", default_value_t = 200)]
+    key_timeout: u64,
+
+    /// run without window, keyboard and audio, then print the screen
+    #[arg(long, conflicts_with = "frontend")]
+    headless: bool,
+
+    /// number of frames to run in headless mode (runs forever if missing)
+    #[arg(long, requires = "headless")]
+    frames: Option<u64>,
+
+    /// key script to replay in headless mode
+    #[arg(long, requires = "headless")]
+    keys: Option<String>,
+
+    /// random generator used by CXNN (splitmix or vip)
+    #[arg(long, value_name = "NAME", default_value = "splitmix")]
+    rng: String,
+
+    /// seed of the random generator (random by default)
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// save state to restore before starting
+    #[arg(long, value_name = "FILE")]
+    load_state: Option<String>,
+
+    /// save the state when the headless run stops
+    #[arg(long, value_name = "FILE", requires = "headless")]
+    save_state: Option<String>,
+
+    /// record the inputs to a movie file, to replay the run later
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["replay", "load_state"])]
+    record: Option<String>,
+
+    /// replay a movie file recorded with --record, with its settings
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["keys", "load_state"])]
+    replay: Option<String>,
+
+    /// pause at start and read debugger commands from the terminal
+    #[arg(long)]
+    debug: bool,
+
+    /// full-screen terminal debugger, instead of the SDL window
+    #[arg(long, conflicts_with_all = ["headless", "frontend", "debug", "gdb"])]
+    tui: bool,
+
+    /// wait for GDB to connect on a TCP address (e.g. 127.0.0.1:1234) and let it drive execution
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "debug")]
+    gdb: Option<String>,
+
+    /// log every executed instruction and the registers after it to a file
+    #[arg(long, value_name = "FILE")]
+    trace: Option<String>,
+
+    /// layout of the trace lines: rchip8 (cycles, disassembly and registers after each
+    /// instruction) or cadmium (state lines of the Cadmium emulator)
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "rchip8",
+        requires = "trace"
+    )]
+    trace_format: trace::Format,
+
+    /// only trace the instructions at addresses in a range, e.g. 0x200-0x2FF
+    #[arg(long, value_name = "START-END", value_parser = trace::parse_range, requires = "trace")]
+    trace_range: Option<RangeInclusive<u16>>,
+
+    /// stop tracing after N logged lines (instructions outside of --trace-range are not counted)
+    #[arg(long, value_name = "N", requires = "trace")]
+    trace_limit: Option<u64>,
+
+    /// ROM to execute (assembly sources ending in .8o are assembled first)
+    rom: String,
+}
+
+/// Frontend of the emulator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Frontend {
+    /// Window, keyboard and audio with SDL.
+    Sdl,
+    /// Terminal, with the screen drawn in text.
+    Tty,
+}
+
+impl FromStr for Frontend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sdl" => Ok(Frontend::Sdl),
+            "tty" => Ok(Frontend::Tty),
+            _ => Err(format!("unknown frontend `{}`, expected `sdl` or `tty`", s)),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct DisasmArgs {
+    /// mnemonic syntax (classic or octo)
+    #[arg(short, long, default_value = "classic")]
+    syntax: Syntax,
+
+    /// decode every valid instruction instead of following the control flow
+    #[arg(long)]
+    no_trace: bool,
+
+    /// ROM to disassemble
     rom: String,
 }
 
+#[derive(Args, Debug)]
+struct AsmArgs {
+    /// output ROM (defaults to the source name with the .ch8 extension)
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// assembly source
+    source: String,
+}
+
 fn print_error_and_quit(s: &str) -> ! {
     eprintln!("{}", s);
     process::exit(1)
 }
 
-fn get_reg(opcode: u16, pos: u8) -> usize {
-    let shift = 4 * (3 - pos);
-    usize::from((opcode & (0x000F << shift)) >> shift)
+fn main() {
+    // parse arguments
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Run(args)) => run(&args),
+        Some(Command::Disasm(args)) => disassemble(&args),
+        Some(Command::Asm(args)) => assemble(&args),
+        None => run(&cli.run.expect("ROM is required without a subcommand")),
+    }
 }
 
-fn get_n(opcode: u16) -> u8 {
-    (opcode & 0xF) as u8
+/// Read a ROM, assembling it first if it is a .8o source.
+fn read_rom(path: &str) -> Vec<u8> {
+    let rom = if Path::new(path).extension().is_some_and(|ext| ext == "8o") {
+        asm::assemble_file(path)
+    } else {
+        fs::read(path).map_err(|err| format!("{}: {}", path, err))
+    };
+    rom.unwrap_or_else(|err| print_error_and_quit(&format!("Error: {}", err)))
 }
 
-fn get_nn(opcode: u16) -> u8 {
-    (opcode & 0xFF) as u8
+fn assemble(args: &AsmArgs) {
+    let rom = asm::assemble_file(&args.source)
+        .unwrap_or_else(|err| print_error_and_quit(&format!("Error: {}", err)));
+    let output = match &args.output {
+        Some(output) => output.clone(),
+        None => Path::new(&args.source)
+            .with_extension("ch8")
+            .to_string_lossy()
+            .into_owned(),
+    };
+    fs::write(&output, rom)
+        .unwrap_or_else(|err| print_error_and_quit(&format!("Error: {}: {}", output, err)));
 }
 
-fn get_nnn(opcode: u16) -> u16 {
-    opcode & 0xFFF
+fn disassemble(args: &DisasmArgs) {
+    let rom = read_rom(&args.rom);
+    for line in disasm::disassemble(&rom, !args.no_trace) {
+        println!("{}", line.format(args.syntax));
+    }
 }
 
-fn main() {
-    // parse arguments
-    let args = Args::parse();
+/// Execute a debugger command and print its output, then the prompt if still paused.
+fn debug_command(debugger: &mut Debugger, chip8: &Chip8, line: &str) {
+    match debugger.command(chip8, line) {
+        Ok(output) => print!("{}", output),
+        Err(err) => println!("{}", err),
+    }
+    if debugger.is_paused() && !debugger.quit_requested {
+        print_prompt();
+    }
+}
+
+/// Print where the debugger paused, if it just did, and the prompt.
+fn debug_report(debugger: &mut Debugger) {
+    if let Some(message) = debugger.take_message() {
+        print!("{}", message);
+        print_prompt();
+    }
+}
+
+fn print_prompt() {
+    print!("(rchip8) ");
+    io::stdout().flush().unwrap_or(());
+}
+
+/// What a run needs besides its arguments and the machine.
+struct Session {
+    /// Hash of the ROM (see `memory::rom_hash`).
+    rom_hash: u64,
+    /// Movie being replayed.
+    replay: Option<Movie>,
+    /// Movie being recorded.
+    recording: Option<Movie>,
+    /// Execution trace.
+    trace: Option<Trace>,
+}
+
+impl Session {
+    /// Save the movie being recorded and complete the trace, if any.
+    fn finish(&mut self, args: &RunArgs, chip8: &Chip8) {
+        if let (Some(movie), Some(path)) = (&self.recording, &args.record) {
+            movie
+                .save(path)
+                .unwrap_or_else(|err| eprintln!("Unable to save movie: {}", err));
+        }
+        if let Some(trace) = &mut self.trace {
+            trace
+                .finish(chip8)
+                .unwrap_or_else(|err| eprintln!("Error: {}", err));
+        }
+    }
+
+    /// Get the keys of a frame, from the replayed movie until its end or else from the user, and
+    /// record them.
+    #[cfg(any(feature = "sdl", feature = "terminal"))]
+    fn next_input(&mut self, frame: usize, user_input: KeyInput) -> KeyInput {
+        let input = match self.replay.as_ref().map(|movie| movie.keys(frame)) {
+            Some(Some(input)) => input,
+            Some(None) => {
+                eprintln!("Replay finished");
+                self.replay = None;
+                user_input
+            }
+            None => user_input,
+        };
+        if let Some(movie) = &mut self.recording {
+            movie.push(input);
+        }
+        input
+    }
+
+    /// Finish the session and exit with an error.
+    fn quit(&mut self, args: &RunArgs, chip8: &Chip8, err: &str) -> ! {
+        self.finish(args, chip8);
+        print_error_and_quit(&format!("Error: {}", err))
+    }
+}
+
+/// Hook called before each instruction: trace it, then check if a debugger pauses on it.
+fn before_instruction(
+    chip8: &Chip8,
+    trace: &mut Option<Trace>,
+    debugger: Option<&mut Debugger>,
+    gdb: Option<&mut GdbStub>,
+) -> bool {
+    if let Some(trace) = trace {
+        trace.record(chip8);
+    }
+    debugger.is_some_and(|debugger| debugger.check(chip8))
+        || gdb.is_some_and(|gdb| gdb.check(chip8))
+}
+
+/// Wait for GDB to connect, if requested.
+fn connect_gdb(args: &RunArgs) -> Option<GdbStub> {
+    args.gdb.as_ref().map(|address| {
+        GdbStub::listen(address)
+            .unwrap_or_else(|err| print_error_and_quit(&format!("Error: {}", err)))
+    })
+}
+
+fn run(args: &RunArgs) {
+    let rom = read_rom(&args.rom);
+    if let Some(images) = &args.vip {
+        run_vip(args, images, &rom);
+        return;
+    }
+    let rom_hash = memory::rom_hash(&rom);
+
+    // a replayed movie comes with its own settings
+    let replay = args.replay.as_ref().map(|path| {
+        let movie = Movie::load(path)
+            .unwrap_or_else(|err| print_error_and_quit(&format!("Error: {}", err)));
+        if movie.rom_hash != rom_hash {
+            print_error_and_quit(&format!(
+                "Error: {}: movie was recorded with a different ROM",
+                path
+            ));
+        }
+        movie
+    });
+
+    // machine
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let mut chip8 = match &replay {
+        Some(movie) => movie.machine(),
+        None => new_machine(args, rom_hash, seed),
+    }
+    .unwrap_or_else(|err| print_error_and_quit(&format!("Error: {}", err)));
+    chip8
+        .load(&rom)
+        .unwrap_or_else(|err| print_error_and_quit(&format!("Error: {}", err)));
+    if let Some(path) = &args.load_state {
+        state::load_file(&mut chip8, rom_hash, path)
+            .unwrap_or_else(|err| print_error_and_quit(&format!("Error: {}", err)));
+    }
+
+    let recording = args
+        .record
+        .as_ref()
+        .map(|_| Movie::new(&chip8, rom_hash, seed));
+
+    let trace = args.trace.as_ref().map(|path| {
+        let mut trace = Trace::create(path)
+            .unwrap_or_else(|err| print_error_and_quit(&format!("Error: {}", err)));
+        trace.set_format(args.trace_format);
+        if let Some(range) = &args.trace_range {
+            trace.set_range(range.clone());
+        }
+        if let Some(limit) = args.trace_limit {
+            trace.set_limit(limit);
+        }
+        trace
+    });
+
+    let session = Session {
+        rom_hash,
+        replay,
+        recording,
+        trace,
+    };
+    if args.headless {
+        run_headless(args, chip8, session);
+    } else if args.tui {
+        run_tui(args, chip8, session);
+    } else if args.frontend == Frontend::Tty {
+        run_tty(args, chip8, session);
+    } else {
+        run_sdl(args, chip8, session);
+    }
+}
+
+/// Create a machine with the settings from the command line.
+fn new_machine(args: &RunArgs, rom_hash: u64, seed: u64) -> Result<Chip8, String> {
+    let mut chip8 = Chip8::with_platform(args.platform, args.freq);
+    if let Some(quirks) = args.quirks {
+        chip8.quirks = quirks;
+    }
+    for spec in &args.quirk {
+        chip8.quirks.set(spec)?;
+    }
+    if args.timing == Timing::Vip && args.platform != Platform::Chip8 {
+        return Err("the VIP timing is only available on the chip8 platform".to_string());
+    }
+    chip8.timing = args.timing;
+    chip8.set_rng(rng::from_name(&args.rng, seed)?);
+
+    // restore the flags saved by the previous runs of the ROM (not in headless runs, which
+    // must be reproducible)
+    if !args.headless && chip8.platform().flag_count() > 0 {
+        chip8.flags = flags::load(rom_hash).unwrap_or_else(|err| {
+            eprintln!("Unable to load flags: {}", err);
+            [0; 16]
+        });
+    }
+    Ok(chip8)
+}
+
+fn run_headless(args: &RunArgs, mut chip8: Chip8, mut session: Session) {
+    let mut headless = match (&args.keys, &session.replay) {
+        (Some(path), _) => Headless::from_script(path)
+            .unwrap_or_else(|err| print_error_and_quit(&format!("Error: {}", err))),
+        (None, Some(movie)) => Headless::with_inputs(movie.frames.clone()),
+        (None, None) => Headless::new(),
+    };
+    // a replay stops at the end of the movie
+    let frames = args.frames.or_else(|| {
+        session
+            .replay
+            .as_ref()
+            .map(|movie| movie.frames.len() as u64)
+    });
+
+    let mut debugger = args.debug.then(Debugger::new);
+    let mut input = io::stdin().lock();
+    let mut gdb = connect_gdb(args);
+
+    while !chip8.exited() && frames.is_none_or(|frames| headless.frame() < frames) {
+        if let Some(debugger) = debugger.as_mut().filter(|d| d.is_paused()) {
+            // the run stops at the end of the commands
+            let mut line = String::new();
+            match input.read_line(&mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => debug_command(debugger, &chip8, line.trim_end()),
+            }
+            if debugger.quit_requested {
+                break;
+            }
+            continue;
+        }
+        if let Some(gdb) = &mut gdb {
+            // blocks while execution is paused
+            gdb.poll(&mut chip8, true);
+            if gdb.quit_requested {
+                break;
+            }
+        }
+
+        let result = headless.run_frame_until(&mut chip8, |chip8| {
+            before_instruction(chip8, &mut session.trace, debugger.as_mut(), gdb.as_mut())
+        });
+        if let Some(debugger) = &mut debugger {
+            debug_report(debugger);
+        }
+        let interrupted = result.unwrap_or_else(|err| session.quit(args, &chip8, &err));
+        if let (false, Some(movie)) = (interrupted, &mut session.recording) {
+            movie.push(headless.input());
+        }
+    }
+    if let Some(gdb) = &mut gdb {
+        gdb.finish();
+    }
+    session.finish(args, &chip8);
+
+    if let Some(path) = &args.save_state {
+        state::save_file(&chip8, session.rom_hash, path)
+            .unwrap_or_else(|err| print_error_and_quit(&format!("Error: {}", err)));
+    }
+
+    print!("{}", chip8.display);
+    println!(
+        "frames: {}, beeps: {} ({} frames)",
+        headless.frame(),
+        headless.beeps(),
+        headless.beep_frames()
+    );
+}
+
+/// Run a ROM on an emulated COSMAC VIP, with the monitor and interpreter images.
+fn run_vip(args: &RunArgs, images: &[String], rom: &[u8]) {
+    let read = |path: &String| {
+        fs::read(path)
+            .unwrap_or_else(|err| print_error_and_quit(&format!("Error: {}: {}", path, err)))
+    };
+    let vip = Vip::new(&read(&images[0]), &read(&images[1]), rom)
+        .unwrap_or_else(|err| print_error_and_quit(&format!("Error: {}", err)));
+    if args.headless {
+        let mut headless = match &args.keys {
+            Some(path) => Headless::from_script(path)
+                .unwrap_or_else(|err| print_error_and_quit(&format!("Error: {}", err))),
+            None => Headless::new(),
+        };
+        let mut vip = vip;
+        while args.frames.is_none_or(|frames| headless.frame() < frames) {
+            headless.run_vip_frame(&mut vip);
+        }
+        print!("{}", vip.display);
+        println!(
+            "frames: {}, beeps: {} ({} frames)",
+            headless.frame(),
+            headless.beeps(),
+            headless.beep_frames()
+        );
+    } else if args.frontend == Frontend::Tty {
+        run_vip_tty(args, vip);
+    } else {
+        run_vip_sdl(args, vip);
+    }
+}
+
+#[cfg(not(feature = "terminal"))]
+fn run_vip_tty(_args: &RunArgs, _vip: Vip) {
+    print_error_and_quit("Error: rchip8 was built without terminal support");
+}
+
+#[cfg(feature = "terminal")]
+fn run_vip_tty(args: &RunArgs, mut vip: Vip) {
+    let key_timeout = Duration::from_millis(args.key_timeout);
+    let mut tty = Tty::new(key_timeout)
+        .unwrap_or_else(|err| print_error_and_quit(&format!("Error: {}", err)));
+    let mut scheduler = Scheduler::new();
+    loop {
+        tty.tick();
+        if tty.quit_requested {
+            break;
+        }
+        vip.set_keys(tty.keys());
+        vip.run_frame();
+        tty.set_sound(vip.sound_active());
+        if vip.display.take_refresh() {
+            if let Err(err) = tty.render(&vip.display) {
+                tty.restore();
+                print_error_and_quit(&format!("Error: {}", err));
+            }
+        }
+        scheduler.wait();
+    }
+    tty.restore();
+}
 
-    // clock frequency
-    let cycle_duration = Duration::new(0, 1_000_000_000u32 / args.freq);
+#[cfg(not(feature = "sdl"))]
+fn run_vip_sdl(_args: &RunArgs, _vip: Vip) {
+    print_error_and_quit("Error: rchip8 was built without SDL support, use --headless");
+}
 
-    // memory, registers and stack
-    let mut memory = [0u8; 4096];
-    let mut v = [0u8; 16];
-    let mut i = 0u16;
-    let mut stack: Vec<u16> = Vec::new();
-    let mut pc = 512u16;
-    memory::load_rom(&mut memory, &args.rom).unwrap_or_else(|err| print_error_and_quit(&err));
-    memory::load_character_set(&mut memory);
+#[cfg(feature = "sdl")]
+fn run_vip_sdl(args: &RunArgs, mut vip: Vip) {
+    let sdl_context = sdl2::init().unwrap_or_else(|err| print_error_and_quit(&err));
+    let mut display =
+        Display::new(&sdl_context, args.pixel).unwrap_or_else(|err| print_error_and_quit(&err));
+    display.render(&vip.display);
+    let mut keyboard = Keyboard::new(&sdl_context).unwrap_or_else(|err| print_error_and_quit(&err));
+    let mut audio = Audio::new(&sdl_context).unwrap_or_else(|err| print_error_and_quit(&err));
+    let mut scheduler = Scheduler::new();
+    loop {
+        keyboard.tick();
+        if keyboard.quit_requested {
+            break;
+        }
+        // hotkeys work on the high-level interpreter only
+        keyboard.take_hotkeys();
+        vip.set_keys(keyboard.keys());
+        vip.run_frame();
+        audio.set_active(vip.sound_active());
+        if vip.display.take_refresh() {
+            display.render(&vip.display);
+        }
+        scheduler.wait();
+    }
+}
+
+#[cfg(not(feature = "terminal"))]
+fn run_tty(_args: &RunArgs, _chip8: Chip8, _session: Session) {
+    print_error_and_quit("Error: rchip8 was built without terminal support");
+}
+
+#[cfg(feature = "terminal")]
+fn run_tty(args: &RunArgs, mut chip8: Chip8, mut session: Session) {
+    if args.debug {
+        print_error_and_quit("Error: --debug reads the terminal, use --tui instead");
+    }
+    let mut gdb = connect_gdb(args);
+    let key_timeout = Duration::from_millis(args.key_timeout);
+    let mut tty = Tty::new(key_timeout)
+        .unwrap_or_else(|err| print_error_and_quit(&format!("Error: {}", err)));
+    let mut frame: usize = 0;
+    let mut mid_frame = false;
+    let mut scheduler = Scheduler::new();
+
+    loop {
+        // process input keys
+        tty.tick();
+        if tty.quit_requested {
+            break;
+        }
+        if let Some(gdb) = &mut gdb {
+            gdb.poll(&mut chip8, false);
+            if gdb.quit_requested {
+                break;
+            }
+        }
+
+        if !gdb.as_ref().is_some_and(GdbStub::is_paused) {
+            // an interrupted frame resumes with the same keys
+            if !mid_frame {
+                chip8.set_input(session.next_input(frame, tty.input()));
+                frame += 1;
+            }
+            let result = chip8.run_frame_until(|chip8| {
+                before_instruction(chip8, &mut session.trace, None, gdb.as_mut())
+            });
+            mid_frame = match result {
+                Ok(interrupted) => interrupted,
+                Err(err) => {
+                    tty.restore();
+                    session.quit(args, &chip8, &err);
+                }
+            };
+            if chip8.take_flags_changed() && session.replay.is_none() {
+                flags::save(session.rom_hash, &chip8.flags).unwrap_or_else(|err| {
+                    eprintln!("Unable to save flags: {}", err);
+                });
+            }
+            if chip8.exited() {
+                break;
+            }
+            tty.set_sound(chip8.sound_active());
+            if chip8.display.take_refresh() {
+                if let Err(err) = tty.render(&chip8.display) {
+                    tty.restore();
+                    session.quit(args, &chip8, &err);
+                }
+            }
+        }
+
+        scheduler.wait();
+    }
+    tty.restore();
+    if let Some(gdb) = &mut gdb {
+        gdb.finish();
+    }
+    session.finish(args, &chip8);
+}
+
+#[cfg(not(feature = "terminal"))]
+fn run_tui(_args: &RunArgs, _chip8: Chip8, _session: Session) {
+    print_error_and_quit("Error: rchip8 was built without terminal support");
+}
+
+#[cfg(feature = "terminal")]
+fn run_tui(args: &RunArgs, mut chip8: Chip8, mut session: Session) {
+    let key_timeout = Duration::from_millis(args.key_timeout);
+    let mut tui = Tui::new(key_timeout)
+        .unwrap_or_else(|err| print_error_and_quit(&format!("Error: {}", err)));
+    let mut debugger = Debugger::new();
+    let mut frame: usize = 0;
+    let mut mid_frame = false;
+    let mut scheduler = Scheduler::new();
+
+    loop {
+        // shortcuts and keypad
+        tui.tick();
+        if let Err(err) = tui.handle_events(&chip8, &mut debugger) {
+            tui.restore();
+            session.quit(args, &chip8, &err);
+        }
+        if debugger.quit_requested {
+            break;
+        }
 
+        if !debugger.is_paused() {
+            // an interrupted frame resumes with the same keys
+            if !mid_frame {
+                chip8.set_input(session.next_input(frame, tui.input()));
+                frame += 1;
+            }
+            let result = chip8.run_frame_until(|chip8| {
+                before_instruction(chip8, &mut session.trace, Some(&mut debugger), None)
+            });
+            mid_frame = match result {
+                Ok(interrupted) => interrupted,
+                Err(err) => {
+                    tui.restore();
+                    session.quit(args, &chip8, &err);
+                }
+            };
+            if chip8.take_flags_changed() && session.replay.is_none() {
+                flags::save(session.rom_hash, &chip8.flags).unwrap_or_else(|err| {
+                    eprintln!("Unable to save flags: {}", err);
+                });
+            }
+            if chip8.exited() {
+                break;
+            }
+        }
+        if let Err(err) = tui.draw(&chip8, &mut debugger, frame) {
+            tui.restore();
+            session.quit(args, &chip8, &err);
+        }
+
+        scheduler.wait();
+    }
+    tui.restore();
+    session.finish(args, &chip8);
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_sdl(_args: &RunArgs, _chip8: Chip8, _session: Session) {
+    print_error_and_quit("Error: rchip8 was built without SDL support, use --headless");
+}
+
+#[cfg(feature = "sdl")]
+fn run_sdl(args: &RunArgs, mut chip8: Chip8, mut session: Session) {
     // initialize sdl
     let sdl_context = sdl2::init().unwrap_or_else(|err| print_error_and_quit(&err));
 
     // create display and show it
     let mut display =
         Display::new(&sdl_context, args.pixel).unwrap_or_else(|err| print_error_and_quit(&err));
-    display.tick();
+    display.render(&chip8.display);
 
     // create keyboard manager
     let mut keyboard = Keyboard::new(&sdl_context).unwrap_or_else(|err| print_error_and_quit(&err));
 
     // create audio device
-    let mut audio =
-        Audio::new(&sdl_context, args.freq).unwrap_or_else(|err| print_error_and_quit(&err));
+    let mut audio = Audio::new(&sdl_context).unwrap_or_else(|err| print_error_and_quit(&err));
+
+    // save state slot used by the hotkeys
+    let mut slot = 0;
+
+    // recent frames, to rewind while Backspace is held
+    let mut rewind = Rewind::new(args.rewind as usize * 60);
 
-    // timer
-    let mut timer = Timer::new(args.freq);
+    // frames executed, to follow the replayed movie
+    let mut frame: usize = 0;
+
+    // debugger, with its commands read from stdin by another thread to keep the window alive
+    let mut debugger = args.debug.then(Debugger::new);
+    let debug_input = args.debug.then(spawn_stdin_reader);
+    let mut gdb = connect_gdb(args);
+    let mut mid_frame = false;
+    let mut scheduler = Scheduler::new();
 
     // main loop
     'running: loop {
-        let start_time = Instant::now();
-
-        // fetch
-        let opcode = ((memory[pc as usize] as u16) << 8) + memory[(pc + 1) as usize] as u16;
-        // decode and execute
-        match opcode & 0xF000 {
-            0x0000 => {
-                if opcode == 0x00E0 {
-                    // disp_clear()
-                    display.clear();
-                    pc += 2;
-                } else if opcode == 0x00EE {
-                    // return;
-                    pc = stack.pop().unwrap();
-                } else {
-                    // machine code routine
-                    stack.push(pc + 2);
-                    pc = get_nnn(opcode);
-                }
-            }
-            0x1000 => {
-                // goto NNN;
-                pc = get_nnn(opcode);
-            }
-            0x2000 => {
-                // *(0xNNN)()
-                stack.push(pc + 2);
-                pc = get_nnn(opcode);
-            }
-            0x3000 => {
-                // if (Vx == NN)
-                if v[get_reg(opcode, 1)] == get_nn(opcode) {
-                    pc += 4;
-                } else {
-                    pc += 2;
-                }
+        // process input keys
+        keyboard.tick();
+        if keyboard.quit_requested {
+            break 'running;
+        }
+        if let Some(gdb) = &mut gdb {
+            gdb.poll(&mut chip8, false);
+            if gdb.quit_requested {
+                break 'running;
             }
-            0x4000 => {
-                // if (Vx != NN)
-                if v[get_reg(opcode, 1)] != get_nn(opcode) {
-                    pc += 4;
-                } else {
-                    pc += 2;
-                }
+        }
+        for hotkey in keyboard.take_hotkeys() {
+            if hotkey == Hotkey::LoadState
+                && (session.replay.is_some() || session.recording.is_some())
+            {
+                eprintln!("Loading states is disabled while recording or replaying a movie");
+                continue;
             }
-            0x5000 => {
-                // if (Vx == Vy)
-                if v[get_reg(opcode, 1)] == v[get_reg(opcode, 2)] {
-                    pc += 4;
-                } else {
-                    pc += 2;
+            handle_hotkey(hotkey, &mut chip8, session.rom_hash, &mut slot);
+        }
+        if keyboard.rewind_held {
+            // play the recorded frames backwards
+            if rewind.step_back(&mut chip8) {
+                display.render(&chip8.display);
+                frame = frame.saturating_sub(1);
+                mid_frame = false;
+                if let Some(movie) = &mut session.recording {
+                    movie.frames.pop();
                 }
             }
-            0x6000 => {
-                // Vx = NN
-                v[get_reg(opcode, 1)] = get_nn(opcode);
-                pc += 2;
-            }
-            0x7000 => {
-                // Vx += NN
-                v[get_reg(opcode, 1)] += get_nn(opcode);
-                pc += 2;
+            audio.set_active(false);
+        } else if let Some(debugger) = debugger.as_mut().filter(|d| d.is_paused()) {
+            // wait for debugger commands
+            audio.set_active(false);
+            while let Some(Ok(line)) = debug_input.as_ref().map(mpsc::Receiver::try_recv) {
+                debug_command(debugger, &chip8, &line);
             }
-            0x8000 => {
-                let x = get_reg(opcode, 1);
-                let y = get_reg(opcode, 2);
-                match opcode & 0x000F {
-                    0x0000 => {
-                        // Vx = Vy
-                        v[x] = v[y];
-                        pc += 2;
-                    }
-                    0x0001 => {
-                        // Vx |= Vy
-                        v[x] |= v[y];
-                        pc += 2;
-                    }
-                    0x0002 => {
-                        // Vx &= Vy
-                        v[x] &= v[y];
-                        pc += 2;
-                    }
-                    0x0003 => {
-                        // Vx ^= Vy
-                        v[x] ^= v[y];
-                        pc += 2;
-                    }
-                    0x0004 => {
-                        // Vx += Vy
-                        let (s, c) = v[x].overflowing_add(v[y]);
-                        v[x] = s;
-                        if c {
-                            v[0xF] = 1;
-                        } else {
-                            v[0xF] = 0;
-                        }
-                        pc += 2;
-                    }
-                    0x0005 => {
-                        // Vx -= Vy
-                        let b = if v[y] > v[x] { 0 } else { 1 };
-                        v[x] -= v[y];
-                        v[0xF] = b;
-                        pc += 2;
-                    }
-                    0x0006 => {
-                        // Vx >>= 1
-                        v[0xF] = v[x] & 0x01;
-                        v[x] >>= 1;
-                        pc += 2;
-                    }
-                    0x0007 => {
-                        // Vx = Vy - Vx
-                        let b = if v[x] > v[y] { 0 } else { 1 };
-                        v[x] = v[y] - v[x];
-                        v[0xF] = b;
-                        pc += 2;
-                    }
-                    0x000E => {
-                        // Vx <<= 1
-                        v[0xF] = v[x] & 0x80;
-                        v[x] <<= 1;
-                        pc += 2;
-                    }
-                    _ => print_error_and_quit(&format!(
-                        "Error: instruction {:#06X} not implemented!",
-                        opcode
-                    )),
-                }
+            if debugger.quit_requested {
+                break 'running;
             }
-            0x9000 => {
-                // if (Vx != Vy)
-                if v[get_reg(opcode, 1)] != v[get_reg(opcode, 2)] {
-                    pc += 4;
-                } else {
-                    pc += 2;
-                }
+        } else if gdb.as_ref().is_some_and(GdbStub::is_paused) {
+            // wait for GDB to resume execution
+            audio.set_active(false);
+        } else {
+            // an interrupted frame resumes with the same keys
+            if !mid_frame {
+                chip8.set_input(session.next_input(frame, keyboard.input()));
+                frame += 1;
             }
-            0xA000 => {
-                // I = NNN
-                i = get_nnn(opcode);
-                pc += 2;
-            }
-            0xB000 => {
-                // PC = V0 + NNN
-                pc = v[0] as u16 + get_nnn(opcode);
+
+            // execute
+            let result = chip8.run_frame_until(|chip8| {
+                before_instruction(chip8, &mut session.trace, debugger.as_mut(), gdb.as_mut())
+            });
+            mid_frame = result.unwrap_or_else(|err| session.quit(args, &chip8, &err));
+            if chip8.take_flags_changed() && session.replay.is_none() {
+                flags::save(session.rom_hash, &chip8.flags).unwrap_or_else(|err| {
+                    eprintln!("Unable to save flags: {}", err);
+                });
             }
-            0xC000 => {
-                // Vx = rand() & NN
-                let r = rand::thread_rng().gen_range(0..=255);
-                v[get_reg(opcode, 1)] = r & get_nn(opcode);
-                pc += 2;
+            if chip8.exited() {
+                break 'running;
             }
-            0xD000 => {
-                // draw(Vx, Vy, N)
-                let x = get_reg(opcode, 1);
-                let y = get_reg(opcode, 2);
-                let n = get_n(opcode) as usize;
-                let mut sprite = vec![0u8; n];
-                for j in 0..n {
-                    sprite[j] = memory[i as usize + j];
-                }
-                if display.draw(v[x], v[y], &sprite) {
-                    v[0xF] = 1;
-                } else {
-                    v[0xF] = 0;
-                }
-                pc += 2;
+            if !mid_frame {
+                rewind.push(&mut chip8);
             }
-            0xE000 => {
-                let x = get_reg(opcode, 1);
-                match opcode & 0x00FF {
-                    0x009E => {
-                        // if (key() == Vx)
-                        if keyboard.is_down(v[x]) {
-                            pc += 4;
-                        } else {
-                            pc += 2;
-                        }
-                    }
-                    0x00A1 => {
-                        // if (key() != Vx)
-                        if !keyboard.is_down(v[x]) {
-                            pc += 4;
-                        } else {
-                            pc += 2;
-                        }
-                    }
-                    _ => print_error_and_quit(&format!(
-                        "Error: instruction {:#06X} not implemented!",
-                        opcode
-                    )),
-                }
+            if let Some(debugger) = debugger.as_mut() {
+                debug_report(debugger);
             }
-            0xF000 => {
-                let x = get_reg(opcode, 1);
-                match opcode & 0x00FF {
-                    0x0007 => {
-                        // Vx = get_delay()
-                        v[x] = timer.get();
-                        pc += 2;
-                    }
-                    0x00A => {
-                        // Vx = get_key()
-                        for k in 0..16 {
-                            if keyboard.is_down(k) {
-                                v[x] = k;
-                                pc += 2;
-                                break;
-                            }
-                        }
-                    }
-                    0x0015 => {
-                        // delay_timer(Vx)
-                        timer.set(x as u8);
-                        pc += 2;
-                    }
-                    0x0018 => {
-                        // sound_timer(Vx)
-                        audio.play_sound(v[x]);
-                        pc += 2;
-                    }
-                    0x001E => {
-                        // I += Vx
-                        i += v[x] as u16;
-                        pc += 2;
-                    }
-                    0x0029 => {
-                        // I = sprite_addr[Vx]
-                        i = v[x] as u16 * 0x5;
-                        pc += 2;
-                    }
-                    0x0033 => {
-                        // set_BCD(Vx)
-                        memory[i as usize] = v[x] / 100;
-                        memory[i as usize + 1] = (v[x] % 100) / 10;
-                        memory[i as usize + 2] = v[x] % 10;
-                        pc += 2;
-                    }
-                    0x0055 => {
-                        // reg_dump(Vx, &I)
-                        for j in 0..=x {
-                            memory[i as usize + j] = v[j];
-                        }
-                        pc += 2;
-                    }
-                    0x0065 => {
-                        // reg_load(Vx, &I)
-                        for j in 0..=x {
-                            v[j] = memory[i as usize + j];
-                        }
-                        pc += 2;
-                    }
-                    _ => print_error_and_quit(&format!(
-                        "Error: instruction {:#06X} not implemented!",
-                        opcode
-                    )),
-                }
+
+            // update components
+            audio.set_pattern(chip8.pattern, chip8.pitch);
+            audio.set_active(chip8.sound_active() && !mid_frame);
+            if chip8.display.take_refresh() {
+                display.render(&chip8.display);
             }
-            _ => print_error_and_quit(&format!(
-                "Error: instruction {:#06X} not implemented!",
-                opcode
-            )),
         }
 
-        // process input keys
-        keyboard.tick();
-        if keyboard.quit_requested {
-            break 'running;
+        // wait for the next frame
+        scheduler.wait();
+    }
+    if let Some(gdb) = &mut gdb {
+        gdb.finish();
+    }
+    session.finish(args, &chip8);
+}
+
+/// Read the lines typed on stdin in a separate thread.
+#[cfg(feature = "sdl")]
+fn spawn_stdin_reader() -> mpsc::Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
         }
+    });
+    receiver
+}
 
-        // update components
-        timer.tick();
-        audio.tick();
-        display.tick();
+#[cfg(feature = "sdl")]
+fn handle_hotkey(hotkey: Hotkey, chip8: &mut Chip8, rom_hash: u64, slot: &mut u8) {
+    match hotkey {
+        Hotkey::PreviousSlot => *slot = (*slot + state::SLOTS - 1) % state::SLOTS,
+        Hotkey::NextSlot => *slot = (*slot + 1) % state::SLOTS,
+        Hotkey::SaveState => match save_slot(chip8, rom_hash, *slot) {
+            Ok(()) => eprintln!("State saved to slot {}", slot),
+            Err(err) => eprintln!("Unable to save state: {}", err),
+        },
+        Hotkey::LoadState => match load_slot(chip8, rom_hash, *slot) {
+            Ok(()) => eprintln!("State loaded from slot {}", slot),
+            Err(err) => eprintln!("Unable to load state: {}", err),
+        },
+    }
+    if let Hotkey::PreviousSlot | Hotkey::NextSlot = hotkey {
+        eprintln!("Slot {}", slot);
+    }
+}
 
-        // sleep
-        let elapsed = start_time.elapsed();
-        if elapsed < cycle_duration {
-            ::std::thread::sleep(cycle_duration - elapsed);
-        }
+#[cfg(feature = "sdl")]
+fn save_slot(chip8: &Chip8, rom_hash: u64, slot: u8) -> Result<(), String> {
+    let path = state::slot_path(rom_hash, slot).ok_or("cannot find the user data directory")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
     }
+    state::save_file(chip8, rom_hash, &path.to_string_lossy())
+}
+
+#[cfg(feature = "sdl")]
+fn load_slot(chip8: &mut Chip8, rom_hash: u64, slot: u8) -> Result<(), String> {
+    let path = state::slot_path(rom_hash, slot).ok_or("cannot find the user data directory")?;
+    state::load_file(chip8, rom_hash, &path.to_string_lossy())
 }
@@ -0,0 +1,326 @@
+/// Memory and I/O devices connected to a CDP1802.
+pub trait Bus {
+    /// Read a byte of memory.
+    fn read(&mut self, address: u16) -> u8;
+    /// Write a byte of memory.
+    fn write(&mut self, address: u16, value: u8);
+    /// `OUT N`: send a byte to the device selected by `N` (1 to 7).
+    fn output(&mut self, port: u8, value: u8);
+    /// `INP N`: read a byte from the device selected by `N` (1 to 7).
+    fn input(&mut self, port: u8) -> u8;
+    /// Check if the external flag `EFn` (1 to 4) is asserted.
+    fn flag(&self, n: u8) -> bool;
+}
+
+/// RCA CDP1802 (COSMAC) CPU.
+///
+/// Instructions take 2 machine cycles, or 3 for the long branches and skips, a machine cycle
+/// being 8 clock cycles. Interrupts and DMA are driven by the machine between instructions,
+/// with `interrupt` and `dma_out`.
+#[derive(Clone, Debug)]
+pub struct Cdp1802 {
+    /// Scratchpad registers `R0`..`RF`.
+    pub r: [u16; 16],
+    /// Data register (accumulator).
+    pub d: u8,
+    /// Data flag (carry, or inverted borrow).
+    pub df: bool,
+    /// Designator of the program counter.
+    pub p: u8,
+    /// Designator of the data pointer.
+    pub x: u8,
+    /// `X` and `P` saved by an interrupt.
+    pub t: u8,
+    /// Interrupts enabled.
+    pub ie: bool,
+    /// Output flip-flop.
+    pub q: bool,
+    idle: bool,
+}
+
+impl Cdp1802 {
+    /// Create a CPU in its reset state, about to execute from address 0 with `R0`.
+    pub fn new() -> Cdp1802 {
+        Cdp1802 {
+            r: [0; 16],
+            d: 0,
+            df: false,
+            p: 0,
+            x: 0,
+            t: 0,
+            ie: true,
+            q: false,
+            idle: false,
+        }
+    }
+
+    /// Check if the CPU is stopped by `IDL`, until the next interrupt or DMA.
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    /// Take an interrupt if they are enabled, returning `true` if it was taken (1 cycle).
+    ///
+    /// `X` and `P` are saved in `T`, and execution continues with `R1` as program counter and
+    /// `R2` as data pointer.
+    pub fn interrupt(&mut self) -> bool {
+        if !self.ie {
+            return false;
+        }
+        self.t = (self.x << 4) | self.p;
+        self.p = 1;
+        self.x = 2;
+        self.ie = false;
+        self.idle = false;
+        true
+    }
+
+    /// Output DMA cycle: read the byte at `R0` for a device and increment `R0` (1 cycle).
+    pub fn dma_out(&mut self, bus: &mut impl Bus) -> u8 {
+        let value = bus.read(self.r[0]);
+        self.r[0] = self.r[0].wrapping_add(1);
+        self.idle = false;
+        value
+    }
+
+    /// Execute an instruction, returning the number of machine cycles it took (1 when idle).
+    pub fn step(&mut self, bus: &mut impl Bus) -> u32 {
+        if self.idle {
+            return 1;
+        }
+        let opcode = self.fetch(bus);
+        let n = opcode & 0xF;
+        let rn = n as usize;
+        match opcode >> 4 {
+            0x0 if n == 0 => self.idle = true,
+            0x0 => self.d = bus.read(self.r[rn]),
+            0x1 => self.r[rn] = self.r[rn].wrapping_add(1),
+            0x2 => self.r[rn] = self.r[rn].wrapping_sub(1),
+            0x3 => {
+                let taken = self.condition(bus, n);
+                self.short_branch(bus, taken);
+            }
+            0x4 => {
+                self.d = bus.read(self.r[rn]);
+                self.r[rn] = self.r[rn].wrapping_add(1);
+            }
+            0x5 => bus.write(self.r[rn], self.d),
+            0x6 => self.input_output(bus, n),
+            0x7 => self.misc(bus, n),
+            0x8 => self.d = self.r[rn] as u8,
+            0x9 => self.d = (self.r[rn] >> 8) as u8,
+            0xA => self.r[rn] = (self.r[rn] & 0xFF00) | self.d as u16,
+            0xB => self.r[rn] = (self.r[rn] & 0x00FF) | (self.d as u16) << 8,
+            0xC => {
+                self.long_branch(bus, n);
+                return 3;
+            }
+            0xD => self.p = n,
+            0xE => self.x = n,
+            _ => self.arithmetic(bus, n),
+        }
+        2
+    }
+
+    fn fetch(&mut self, bus: &mut impl Bus) -> u8 {
+        let p = self.p as usize;
+        let value = bus.read(self.r[p]);
+        self.r[p] = self.r[p].wrapping_add(1);
+        value
+    }
+
+    /// Condition of the branches and skips, the upper half of `n` negating the lower one.
+    fn condition(&self, bus: &impl Bus, n: u8) -> bool {
+        let value = match n & 0x7 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            flag => bus.flag(flag - 3),
+        };
+        value != (n & 0x8 != 0)
+    }
+
+    /// `3N`: replace the low byte of the program counter with the immediate byte if taken.
+    fn short_branch(&mut self, bus: &mut impl Bus, taken: bool) {
+        let p = self.p as usize;
+        if taken {
+            let target = bus.read(self.r[p]);
+            self.r[p] = (self.r[p] & 0xFF00) | target as u16;
+        } else {
+            self.r[p] = self.r[p].wrapping_add(1);
+        }
+    }
+
+    /// `CN`: long branches, long skips and `NOP`.
+    fn long_branch(&mut self, bus: &mut impl Bus, n: u8) {
+        let p = self.p as usize;
+        let skip = match n {
+            // NOP
+            0x4 => return,
+            0x5 => !self.q,
+            0x6 => self.d != 0,
+            0x7 => !self.df,
+            0xC => self.ie,
+            0xD => self.q,
+            0xE => self.d == 0,
+            0xF => self.df,
+            _ => {
+                if self.condition(bus, n) {
+                    let high = bus.read(self.r[p]);
+                    let low = bus.read(self.r[p].wrapping_add(1));
+                    self.r[p] = (high as u16) << 8 | low as u16;
+                } else {
+                    self.r[p] = self.r[p].wrapping_add(2);
+                }
+                return;
+            }
+        };
+        if skip {
+            self.r[p] = self.r[p].wrapping_add(2);
+        }
+    }
+
+    /// `6N`: `IRX`, `OUT` and `INP`.
+    fn input_output(&mut self, bus: &mut impl Bus, n: u8) {
+        let x = self.x as usize;
+        match n {
+            0 => self.r[x] = self.r[x].wrapping_add(1),
+            1..=7 => {
+                let value = bus.read(self.r[x]);
+                bus.output(n, value);
+                self.r[x] = self.r[x].wrapping_add(1);
+            }
+            // 68 has no effect on the 1802
+            8 => (),
+            _ => {
+                let value = bus.input(n - 8);
+                bus.write(self.r[x], value);
+                self.d = value;
+            }
+        }
+    }
+
+    /// `7N`: returns, stack operations, arithmetic with carry and `Q`.
+    fn misc(&mut self, bus: &mut impl Bus, n: u8) {
+        let x = self.x as usize;
+        match n {
+            0x0 | 0x1 => {
+                let value = bus.read(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+                self.x = value >> 4;
+                self.p = value & 0xF;
+                self.ie = n == 0;
+            }
+            0x2 => {
+                self.d = bus.read(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+            }
+            0x3 => {
+                bus.write(self.r[x], self.d);
+                self.r[x] = self.r[x].wrapping_sub(1);
+            }
+            0x4 => {
+                let value = bus.read(self.r[x]);
+                self.add(value, self.df);
+            }
+            0x5 => {
+                let value = bus.read(self.r[x]);
+                self.subtract(value, self.d);
+            }
+            0x6 => {
+                let carry = self.d & 1 != 0;
+                self.d = self.d >> 1 | (self.df as u8) << 7;
+                self.df = carry;
+            }
+            0x7 => {
+                let value = bus.read(self.r[x]);
+                self.subtract(self.d, value);
+            }
+            0x8 => bus.write(self.r[x], self.t),
+            0x9 => {
+                self.t = (self.x << 4) | self.p;
+                bus.write(self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            0xA => self.q = false,
+            0xB => self.q = true,
+            0xC => {
+                let value = self.fetch(bus);
+                self.add(value, self.df);
+            }
+            0xD => {
+                let value = self.fetch(bus);
+                self.subtract(value, self.d);
+            }
+            0xE => {
+                let carry = self.d & 0x80 != 0;
+                self.d = self.d << 1 | self.df as u8;
+                self.df = carry;
+            }
+            _ => {
+                let value = self.fetch(bus);
+                self.subtract(self.d, value);
+            }
+        }
+    }
+
+    /// `FN`: logic and arithmetic, with `M(R(X))` or an immediate byte.
+    fn arithmetic(&mut self, bus: &mut impl Bus, n: u8) {
+        // shifts have no operand
+        match n {
+            0x6 => {
+                self.df = self.d & 1 != 0;
+                self.d >>= 1;
+                return;
+            }
+            0xE => {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+                return;
+            }
+            _ => (),
+        }
+        let value = if n & 0x8 != 0 {
+            self.fetch(bus)
+        } else {
+            bus.read(self.r[self.x as usize])
+        };
+        match n & 0x7 {
+            0 => self.d = value,
+            1 => self.d |= value,
+            2 => self.d &= value,
+            3 => self.d ^= value,
+            4 => self.add(value, false),
+            5 => {
+                self.df = true;
+                self.subtract(value, self.d);
+            }
+            _ => {
+                self.df = true;
+                self.subtract(self.d, value);
+            }
+        }
+    }
+
+    /// `D = D + value + carry`, `DF` being the carry out.
+    fn add(&mut self, value: u8, carry: bool) {
+        let sum = self.d as u16 + value as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    /// `D = a - b`, with a borrow if `DF` is clear; `DF` is then set if there was no borrow.
+    fn subtract(&mut self, a: u8, b: u8) {
+        let difference = a as i16 - b as i16 - !self.df as i16;
+        self.d = difference as u8;
+        self.df = difference >= 0;
+    }
+}
+
+impl Default for Cdp1802 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,728 @@
+use std::ops::Range;
+
+use super::commons::CanTick;
+use super::framebuffer::Framebuffer;
+use super::instruction::Instruction;
+use super::memory;
+use super::platform::Platform;
+use super::quirks::{IndexIncrement, Quirks};
+use super::rng::{Rng, SplitMix64};
+use super::state::{Reader, Snapshot, Writer};
+use super::timer::Timer;
+use super::timing::{self, Timing};
+
+/// Memory ranges read and written by the last executed instruction (for watchpoints).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Accesses {
+    pub read: Option<Range<usize>>,
+    pub write: Option<Range<usize>>,
+}
+
+/// Keys of a frame: the keys held, and the ones pressed or released since the previous frame.
+///
+/// A key both pressed and released was tapped during the frame, frontends keep it down until
+/// the next one so that `EX9E` can see it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeyInput {
+    pub down: [bool; 16],
+    pub pressed: [bool; 16],
+    pub released: [bool; 16],
+}
+
+/// A CHIP-8 machine: registers, memory, stack and timers.
+///
+/// The machine does not perform any I/O by itself: keys are fed with `set_input`,
+/// the framebuffer is exposed through `display` and the state of the sound timer
+/// through `sound_active`, so that any frontend can drive it.
+#[derive(Clone)]
+pub struct Chip8 {
+    /// Main memory (4KB, or 64KB on XO-CHIP).
+    pub memory: Vec<u8>,
+    /// General purpose registers `V0`..`VF`.
+    pub v: [u8; 16],
+    /// Index register.
+    pub i: u16,
+    /// Program counter.
+    pub pc: u16,
+    /// Return addresses of the active subroutine calls.
+    pub stack: Vec<u16>,
+    /// Delay timer.
+    pub timer: Timer,
+    /// Sound timer.
+    pub sound: Timer,
+    /// Screen contents.
+    pub display: Framebuffer,
+    /// Behavior of the ambiguous instructions.
+    pub quirks: Quirks,
+    /// Timing model, deciding how many instructions run in each frame.
+    pub timing: Timing,
+    /// Audio pattern loaded with `F002`, if any (XO-CHIP).
+    pub pattern: Option<[u8; 16]>,
+    /// Playback pitch of the audio pattern, set with `FX3A` (XO-CHIP).
+    pub pitch: u8,
+    /// RPL user flags, written by `FX75` and read by `FX85` (SUPER-CHIP).
+    ///
+    /// Frontends are expected to persist them, see `take_flags_changed`.
+    pub flags: [u8; 16],
+    flags_changed: bool,
+    rng: Box<dyn Rng>,
+    keys: [bool; 16],
+    /// Keys pressed since the start of the frame.
+    pressed: [bool; 16],
+    /// Keys released since the start of the frame.
+    released: [bool; 16],
+    key_wait: KeyWait,
+    platform: Platform,
+    exited: bool,
+    freq: u32,
+    frame_remainder: u32,
+    frame_cycles: u32,
+    /// Machine cycles of the last instruction left to charge to the next frame (VIP timing).
+    cycle_debt: u32,
+    accesses: Accesses,
+}
+
+impl Chip8 {
+    /// Create a new CHIP-8 machine running at the given CPU frequency, with the character set loaded.
+    pub fn new(freq: u32) -> Chip8 {
+        Chip8::with_platform(Platform::Chip8, freq)
+    }
+
+    /// Create a new machine for the given platform, with its default quirks.
+    pub fn with_platform(platform: Platform, freq: u32) -> Chip8 {
+        let mut memory = vec![0u8; platform.memory_size()];
+        memory::load_character_set(&mut memory);
+        Chip8 {
+            memory,
+            v: [0; 16],
+            i: 0,
+            pc: memory::PROGRAM_START as u16,
+            stack: Vec::new(),
+            timer: Timer::new(),
+            sound: Timer::new(),
+            display: Framebuffer::new(),
+            quirks: platform.default_quirks(),
+            timing: Timing::Fixed,
+            pattern: None,
+            pitch: 64,
+            flags: [0; 16],
+            flags_changed: false,
+            rng: Box::new(SplitMix64::default()),
+            keys: [false; 16],
+            pressed: [false; 16],
+            released: [false; 16],
+            key_wait: KeyWait::Idle,
+            platform,
+            exited: false,
+            freq,
+            frame_remainder: 0,
+            frame_cycles: 0,
+            cycle_debt: 0,
+            accesses: Accesses::default(),
+        }
+    }
+
+    /// Load a program into memory.
+    pub fn load(&mut self, rom: &[u8]) -> Result<(), String> {
+        memory::load_program(&mut self.memory, rom)
+    }
+
+    /// Load a program from a file into memory.
+    pub fn load_rom(&mut self, rom_path: &str) -> Result<(), String> {
+        memory::load_rom(&mut self.memory, rom_path)
+    }
+
+    /// Set the keys of the next frame.
+    pub fn set_input(&mut self, input: KeyInput) {
+        self.keys = input.down;
+        for k in 0..16 {
+            self.pressed[k] |= input.pressed[k];
+            self.released[k] |= input.released[k];
+        }
+    }
+
+    /// Press or release a single key.
+    pub fn set_key(&mut self, k: u8, down: bool) {
+        let k = k as usize;
+        if down {
+            self.pressed[k] |= !self.keys[k];
+        } else {
+            self.released[k] |= self.keys[k];
+        }
+        self.keys[k] = down;
+    }
+
+    /// Get the state of all the 16 keys.
+    pub fn keys(&self) -> [bool; 16] {
+        self.keys
+    }
+
+    /// CPU frequency, in Hz.
+    pub fn freq(&self) -> u32 {
+        self.freq
+    }
+
+    /// Random number generator used by `CXNN`.
+    pub fn rng(&self) -> &dyn Rng {
+        self.rng.as_ref()
+    }
+
+    /// Replace the random number generator used by `CXNN` (SplitMix64 with seed 0 by default).
+    pub fn set_rng(&mut self, rng: Box<dyn Rng>) {
+        self.rng = rng;
+    }
+
+    /// Reset the random number generator with a seed, to make runs reproducible.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng.seed(seed);
+    }
+
+    /// Platform emulated by the machine.
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Check if the program exited (with `00FD`); an exited machine does not execute anymore.
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Check if the sound timer is running, i.e. if a beep should be playing.
+    pub fn sound_active(&self) -> bool {
+        self.sound.get() > 0
+    }
+
+    /// Check if the RPL user flags changed since the last call, and reset the flag.
+    pub fn take_flags_changed(&mut self) -> bool {
+        let changed = self.flags_changed;
+        self.flags_changed = false;
+        changed
+    }
+
+    /// Get the memory accesses of the last executed instruction.
+    pub fn accesses(&self) -> &Accesses {
+        &self.accesses
+    }
+
+    /// Execute instructions for one 60Hz frame, then decrement the timers.
+    ///
+    /// The number of instructions depends on the CPU frequency; when it is not a multiple of 60,
+    /// the remainder is carried over to the following frames.
+    pub fn run_frame(&mut self) -> Result<(), String> {
+        self.run_frame_until(|_| false)?;
+        Ok(())
+    }
+
+    /// Execute instructions for one 60Hz frame, stopping before any instruction for which `stop`
+    /// returns `true`.
+    ///
+    /// Returns `true` if the frame was interrupted; the next call then resumes it, starting with
+    /// the instruction it stopped at (without calling `stop` on it again). The timers are only
+    /// decremented once the frame is complete.
+    pub fn run_frame_until(
+        &mut self,
+        mut stop: impl FnMut(&Chip8) -> bool,
+    ) -> Result<bool, String> {
+        if self.frame_cycles == 0 {
+            self.start_frame();
+        } else {
+            // resume the interrupted frame
+            self.step_in_frame()?;
+        }
+        while self.frame_cycles > 0 && !self.exited {
+            if stop(self) {
+                return Ok(true);
+            }
+            self.step_in_frame()?;
+        }
+        self.frame_cycles = 0;
+        self.pressed = [false; 16];
+        self.released = [false; 16];
+        self.timer.tick();
+        self.sound.tick();
+        Ok(false)
+    }
+
+    /// Set the budget of the frame: a number of instructions, or of machine cycles with the VIP
+    /// timing.
+    fn start_frame(&mut self) {
+        match self.timing {
+            Timing::Fixed => {
+                self.frame_remainder += self.freq;
+                self.frame_cycles = self.frame_remainder / 60;
+                self.frame_remainder %= 60;
+            }
+            Timing::Vip => {
+                let budget = timing::VIP_FRAME_CYCLES - timing::VIP_DISPLAY_CYCLES;
+                self.frame_cycles = budget.saturating_sub(self.cycle_debt);
+                self.cycle_debt = self.cycle_debt.saturating_sub(budget);
+            }
+        }
+    }
+
+    /// Execute an instruction and charge it to the budget of the frame.
+    fn step_in_frame(&mut self) -> Result<(), String> {
+        let (v, i, pc) = (self.v, self.i, self.pc);
+        let Some(instruction) = self.step_instruction()? else {
+            self.frame_cycles = 0;
+            return Ok(());
+        };
+        let draw = matches!(instruction, Instruction::Draw { .. });
+        match self.timing {
+            // with the display wait quirk, a draw ends the frame
+            Timing::Fixed if draw && self.quirks.display_wait => self.frame_cycles = 0,
+            Timing::Fixed => self.frame_cycles -= 1,
+            Timing::Vip => {
+                let skipped = self.pc != pc.wrapping_add(instruction.size() as u16);
+                let cycles = timing::vip_cycles(&instruction, &v, i, skipped);
+                if draw {
+                    // the interpreter always waits for the vertical interrupt, then draws
+                    self.cycle_debt = cycles;
+                    self.frame_cycles = 0;
+                } else if cycles > self.frame_cycles {
+                    // carried over to the next frame
+                    self.cycle_debt = cycles - self.frame_cycles;
+                    self.frame_cycles = 0;
+                } else {
+                    self.frame_cycles -= cycles;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetch, decode and execute a single instruction.
+    ///
+    /// The timers are not updated, they are decremented at the end of each frame.
+    pub fn step(&mut self) -> Result<(), String> {
+        self.step_instruction()?;
+        Ok(())
+    }
+
+    /// Execute a single instruction, returning it unless the program exited.
+    fn step_instruction(&mut self) -> Result<Option<Instruction>, String> {
+        if self.exited {
+            return Ok(None);
+        }
+        self.accesses = Accesses::default();
+        let instruction = self.execute()?;
+        self.rng.tick();
+        Ok(Some(instruction))
+    }
+
+    fn fetch(&self, address: u16) -> Result<u16, String> {
+        let address = address as usize;
+        if address + 1 >= self.memory.len() {
+            return Err(format!("program counter {:#06X} out of memory", address));
+        }
+        Ok(((self.memory[address] as u16) << 8) + self.memory[address + 1] as u16)
+    }
+
+    /// Decode the instruction at an address, if valid (for debugging tools).
+    pub fn instruction_at(&self, address: u16) -> Option<Instruction> {
+        let opcode = self.fetch(address).ok()?;
+        if opcode == 0xF000 {
+            Instruction::decode_long(opcode, self.fetch(address.wrapping_add(2)).ok()?).ok()
+        } else {
+            Instruction::decode(opcode).ok()
+        }
+    }
+
+    /// Size of the instruction at the program counter, used to skip it.
+    fn next_size(&self) -> u16 {
+        // only XO-CHIP has four bytes instructions
+        match self.platform {
+            Platform::XoChip if self.fetch(self.pc) == Ok(0xF000) => 4,
+            _ => 2,
+        }
+    }
+
+    fn execute(&mut self) -> Result<Instruction, String> {
+        let opcode = self.fetch(self.pc)?;
+        let instruction = if opcode == 0xF000 {
+            Instruction::decode_long(opcode, self.fetch(self.pc.wrapping_add(2))?)
+        } else {
+            Instruction::decode(opcode)
+        }
+        .map_err(|e| e.to_string())?;
+        if !self.platform.supports(&instruction) {
+            return Err(format!("instruction {:#06X} not implemented!", opcode));
+        }
+        self.pc = self.pc.wrapping_add(instruction.size() as u16);
+        let skip = self.next_size();
+        let v = &mut self.v;
+        let i = self.i as usize;
+        match instruction {
+            Instruction::Clear => self.display.clear(),
+            Instruction::ScrollDown { n } => self.display.scroll_down(n as usize),
+            Instruction::ScrollRight => self.display.scroll_right(4),
+            Instruction::ScrollLeft => self.display.scroll_left(4),
+            Instruction::Exit => {
+                self.pc = self.pc.wrapping_sub(2);
+                self.exited = true;
+            }
+            Instruction::LowRes => self.display.set_hires(false),
+            Instruction::HighRes => self.display.set_hires(true),
+            Instruction::Return => {
+                self.pc = self.stack.pop().ok_or("return with empty stack")?;
+            }
+            Instruction::Sys { nnn } | Instruction::Call { nnn } => {
+                self.stack.push(self.pc);
+                self.pc = nnn;
+            }
+            Instruction::Jump { nnn } => self.pc = nnn,
+            Instruction::SkipEqImm { x, nn } => {
+                if v[x as usize] == nn {
+                    self.pc = self.pc.wrapping_add(skip);
+                }
+            }
+            Instruction::SkipNeImm { x, nn } => {
+                if v[x as usize] != nn {
+                    self.pc = self.pc.wrapping_add(skip);
+                }
+            }
+            Instruction::SkipEqReg { x, y } => {
+                if v[x as usize] == v[y as usize] {
+                    self.pc = self.pc.wrapping_add(skip);
+                }
+            }
+            Instruction::LoadImm { x, nn } => v[x as usize] = nn,
+            Instruction::AddImm { x, nn } => v[x as usize] = v[x as usize].wrapping_add(nn),
+            Instruction::Move { x, y } => v[x as usize] = v[y as usize],
+            Instruction::Or { x, y } => {
+                v[x as usize] |= v[y as usize];
+                if self.quirks.vf_reset {
+                    v[0xF] = 0;
+                }
+            }
+            Instruction::And { x, y } => {
+                v[x as usize] &= v[y as usize];
+                if self.quirks.vf_reset {
+                    v[0xF] = 0;
+                }
+            }
+            Instruction::Xor { x, y } => {
+                v[x as usize] ^= v[y as usize];
+                if self.quirks.vf_reset {
+                    v[0xF] = 0;
+                }
+            }
+            Instruction::Add { x, y } => {
+                let (s, c) = v[x as usize].overflowing_add(v[y as usize]);
+                v[x as usize] = s;
+                v[0xF] = c as u8;
+            }
+            Instruction::Sub { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let b = if v[y] > v[x] { 0 } else { 1 };
+                v[x] = v[x].wrapping_sub(v[y]);
+                v[0xF] = b;
+            }
+            Instruction::ShiftRight { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let src = if self.quirks.shift_vy { y } else { x };
+                let bit = v[src] & 0x01;
+                v[x] = v[src] >> 1;
+                v[0xF] = bit;
+            }
+            Instruction::SubReversed { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let b = if v[x] > v[y] { 0 } else { 1 };
+                v[x] = v[y].wrapping_sub(v[x]);
+                v[0xF] = b;
+            }
+            Instruction::ShiftLeft { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let src = if self.quirks.shift_vy { y } else { x };
+                let bit = v[src] >> 7;
+                v[x] = v[src] << 1;
+                v[0xF] = bit;
+            }
+            Instruction::SkipNeReg { x, y } => {
+                if v[x as usize] != v[y as usize] {
+                    self.pc = self.pc.wrapping_add(skip);
+                }
+            }
+            Instruction::LoadI { nnn } => self.i = nnn,
+            Instruction::JumpOffset { nnn } => {
+                let offset = if self.quirks.jump_vx {
+                    v[(nnn >> 8) as usize]
+                } else {
+                    v[0]
+                };
+                self.pc = offset as u16 + nnn;
+            }
+            Instruction::Random { x, nn } => {
+                v[x as usize] = self.rng.next_u8(&self.memory) & nn;
+            }
+            Instruction::Draw { x, y, n } => {
+                let hires = self.display.is_hires();
+                let big = n == 0 && self.platform != Platform::Chip8;
+                let tall = big && !hires && self.quirks.lores_tall_sprite;
+                let wide = big && !tall;
+                let height = if big { 16 } else { n as usize };
+                let len = if wide { 32 } else { height } * self.display.plane_count();
+                let sprite = self
+                    .memory
+                    .get(i..i + len)
+                    .ok_or_else(|| format!("sprite at {:#06X} out of memory", i))?;
+                self.accesses.read = Some(i..i + len);
+                let (vx, vy, wrap) = (v[x as usize], v[y as usize], self.quirks.wrap);
+                let collisions = if wide {
+                    self.display.draw_wide(vx, vy, sprite, wrap)
+                } else {
+                    self.display.draw(vx, vy, sprite, wrap)
+                };
+                v[0xF] = if hires && self.quirks.collision_rows {
+                    // rows past the bottom of the screen count as collisions
+                    let top = vy as usize % self.display.height();
+                    let clipped = if wrap {
+                        0
+                    } else {
+                        (top + height).saturating_sub(self.display.height())
+                    };
+                    (collisions + clipped) as u8
+                } else {
+                    (collisions > 0) as u8
+                };
+            }
+            Instruction::SkipKey { x } => {
+                if self.keys[(v[x as usize] & 0xF) as usize] {
+                    self.pc = self.pc.wrapping_add(skip);
+                }
+            }
+            Instruction::SkipNotKey { x } => {
+                if !self.keys[(v[x as usize] & 0xF) as usize] {
+                    self.pc = self.pc.wrapping_add(skip);
+                }
+            }
+            Instruction::GetDelay { x } => v[x as usize] = self.timer.get(),
+            Instruction::WaitKey { x } if self.quirks.wait_key_down => {
+                // re-executed until a key is down
+                match self.keys.iter().position(|&k| k) {
+                    Some(k) => v[x as usize] = k as u8,
+                    None => self.pc = self.pc.wrapping_sub(2),
+                }
+            }
+            Instruction::WaitKey { x } => {
+                // re-executed until a key is pressed after the start of the wait, then released
+                self.key_wait = match self.key_wait {
+                    KeyWait::Idle => {
+                        self.pressed = [false; 16];
+                        self.released = [false; 16];
+                        KeyWait::Press
+                    }
+                    KeyWait::Press => match self.pressed.iter().position(|&k| k) {
+                        Some(k) => KeyWait::Release(k as u8),
+                        None => KeyWait::Press,
+                    },
+                    KeyWait::Release(k) if self.released[k as usize] => {
+                        v[x as usize] = k;
+                        KeyWait::Idle
+                    }
+                    wait => wait,
+                };
+                if self.key_wait != KeyWait::Idle {
+                    self.pc = self.pc.wrapping_sub(2);
+                }
+            }
+            Instruction::SetDelay { x } => self.timer.set(v[x as usize]),
+            Instruction::SetSound { x } => self.sound.set(v[x as usize]),
+            Instruction::AddI { x } => self.i = self.i.wrapping_add(v[x as usize] as u16),
+            Instruction::Font { x } => {
+                self.i = (memory::FONT_START + (v[x as usize] & 0xF) as usize * 5) as u16
+            }
+            Instruction::BigFont { x } => {
+                self.i = (memory::BIG_FONT_START + (v[x as usize] & 0xF) as usize * 10) as u16
+            }
+            Instruction::Bcd { x } => {
+                let digits = [
+                    v[x as usize] / 100,
+                    (v[x as usize] % 100) / 10,
+                    v[x as usize] % 10,
+                ];
+                self.memory_mut(i, 3)?.copy_from_slice(&digits);
+                self.accesses.write = Some(i..i + 3);
+            }
+            Instruction::Store { x } => {
+                let x = x as usize;
+                let registers = self.v;
+                self.memory_mut(i, x + 1)?.copy_from_slice(&registers[..=x]);
+                self.accesses.write = Some(i..i + x + 1);
+                self.increment_index(x);
+            }
+            Instruction::Load { x } => {
+                let x = x as usize;
+                let data = self
+                    .memory
+                    .get(i..=i + x)
+                    .ok_or_else(|| format!("registers load at {:#06X} out of memory", i))?;
+                v[..=x].copy_from_slice(data);
+                self.accesses.read = Some(i..i + x + 1);
+                self.increment_index(x);
+            }
+            Instruction::StoreRange { x, y } => {
+                for (offset, r) in register_range(x, y).enumerate() {
+                    *self.byte_at(i + offset)? = self.v[r];
+                }
+                self.accesses.write = Some(i..i + register_range(x, y).count());
+            }
+            Instruction::LoadRange { x, y } => {
+                for (offset, r) in register_range(x, y).enumerate() {
+                    self.v[r] = *self.byte_at(i + offset)?;
+                }
+                self.accesses.read = Some(i..i + register_range(x, y).count());
+            }
+            Instruction::LoadILong { nnnn } => self.i = nnnn,
+            Instruction::Plane { n } => self.display.set_planes(n),
+            Instruction::Audio => {
+                let pattern = self
+                    .memory
+                    .get(i..i + 16)
+                    .ok_or_else(|| format!("audio pattern at {:#06X} out of memory", i))?;
+                self.pattern = Some(pattern.try_into().unwrap());
+                self.accesses.read = Some(i..i + 16);
+            }
+            Instruction::Pitch { x } => self.pitch = v[x as usize],
+            Instruction::SaveFlags { x } => {
+                let x = x as usize;
+                self.flags[..=x].copy_from_slice(&v[..=x]);
+                self.flags_changed = true;
+            }
+            Instruction::LoadFlags { x } => {
+                let x = x as usize;
+                v[..=x].copy_from_slice(&self.flags[..=x]);
+            }
+        }
+        Ok(instruction)
+    }
+
+    fn memory_mut(&mut self, address: usize, len: usize) -> Result<&mut [u8], String> {
+        self.memory
+            .get_mut(address..address + len)
+            .ok_or_else(|| format!("write of {} bytes at {:#06X} out of memory", len, address))
+    }
+
+    fn byte_at(&mut self, address: usize) -> Result<&mut u8, String> {
+        self.memory
+            .get_mut(address)
+            .ok_or_else(|| format!("address {:#06X} out of memory", address))
+    }
+
+    /// Update `I` after `FX55`/`FX65`, according to the quirks.
+    fn increment_index(&mut self, x: usize) {
+        match self.quirks.memory {
+            IndexIncrement::Unchanged => (),
+            IndexIncrement::ByX => self.i = self.i.wrapping_add(x as u16),
+            IndexIncrement::ByXPlusOne => self.i = self.i.wrapping_add(x as u16 + 1),
+        }
+    }
+}
+
+/// Progress of `FX0A`, waiting for a key to be pressed and released.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KeyWait {
+    Idle,
+    /// Waiting for a key to be pressed.
+    Press,
+    /// Waiting for the pressed key to be released.
+    Release(u8),
+}
+
+/// Everything but the keys, the quirks and the CPU frequency, which are settings of the frontend.
+impl Snapshot for Chip8 {
+    fn save(&self, w: &mut Writer) {
+        w.u8(self.platform as u8);
+        w.u32(self.memory.len() as u32);
+        w.bytes(&self.memory);
+        w.bytes(&self.v);
+        w.u16(self.i);
+        w.u16(self.pc);
+        w.u16(self.stack.len() as u16);
+        for &address in &self.stack {
+            w.u16(address);
+        }
+        self.timer.save(w);
+        self.sound.save(w);
+        self.display.save(w);
+        match self.pattern {
+            Some(pattern) => {
+                w.bool(true);
+                w.bytes(&pattern);
+            }
+            None => w.bool(false),
+        }
+        w.u8(self.pitch);
+        w.bytes(&self.flags);
+        self.rng.save(w);
+        w.bool(self.exited);
+        w.u32(self.frame_remainder);
+        w.u32(self.frame_cycles);
+        w.u32(self.cycle_debt);
+        match self.key_wait {
+            KeyWait::Idle => w.u8(0),
+            KeyWait::Press => w.u8(1),
+            KeyWait::Release(k) => w.u8(0x10 | k),
+        }
+        for (&pressed, &released) in self.pressed.iter().zip(&self.released) {
+            w.bool(pressed);
+            w.bool(released);
+        }
+    }
+
+    fn restore(&mut self, r: &mut Reader) -> Result<(), String> {
+        if r.u8()? != self.platform as u8 {
+            return Err("save state is for a different platform".to_string());
+        }
+        let len = r.u32()? as usize;
+        if len != self.memory.len() {
+            return Err(format!(
+                "save state has {} bytes of memory, expected {}",
+                len,
+                self.memory.len()
+            ));
+        }
+        self.memory.copy_from_slice(r.bytes(len)?);
+        self.v.copy_from_slice(r.bytes(16)?);
+        self.i = r.u16()?;
+        self.pc = r.u16()?;
+        let depth = r.u16()?;
+        self.stack = (0..depth).map(|_| r.u16()).collect::<Result<_, _>>()?;
+        self.timer.restore(r)?;
+        self.sound.restore(r)?;
+        self.display.restore(r)?;
+        self.pattern = match r.bool()? {
+            true => Some(r.bytes(16)?.try_into().unwrap()),
+            false => None,
+        };
+        self.pitch = r.u8()?;
+        self.flags.copy_from_slice(r.bytes(16)?);
+        self.rng.restore(r)?;
+        self.exited = r.bool()?;
+        self.frame_remainder = r.u32()?;
+        self.frame_cycles = r.u32()?;
+        self.cycle_debt = r.u32()?;
+        self.key_wait = match r.u8()? {
+            0 => KeyWait::Idle,
+            1 => KeyWait::Press,
+            k @ 0x10..=0x1F => KeyWait::Release(k & 0xF),
+            k => return Err(format!("invalid key wait state {:#04X} in save state", k)),
+        };
+        for (pressed, released) in self.pressed.iter_mut().zip(self.released.iter_mut()) {
+            *pressed = r.bool()?;
+            *released = r.bool()?;
+        }
+        self.accesses = Accesses::default();
+        Ok(())
+    }
+}
+
+/// Registers from `Vx` to `Vy`, in descending order if `x > y`.
+fn register_range(x: u8, y: u8) -> Box<dyn Iterator<Item = usize>> {
+    let (x, y) = (x as usize, y as usize);
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
+}
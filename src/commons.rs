@@ -1,5 +1,38 @@
+use std::env;
+use std::path::PathBuf;
+
 /// Common trait for components that have to do something each cycle.
 pub trait CanTick {
     /// Perform actions for this cycle.
     fn tick(&mut self);
 }
+
+/// Parse a number (an address, a length...) written in decimal, or in hexadecimal with `0x`.
+pub fn parse_number(text: &str) -> Result<u16, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number `{}`", text))
+}
+
+/// Directory where the emulator keeps its files (flags, save states).
+///
+/// This is `$XDG_DATA_HOME/rchip8` (defaulting to `~/.local/share/rchip8`), or
+/// `%APPDATA%\rchip8` on Windows.
+pub fn data_dir() -> Option<PathBuf> {
+    base_data_dir().map(|dir| dir.join("rchip8"))
+}
+
+#[cfg(windows)]
+fn base_data_dir() -> Option<PathBuf> {
+    env::var_os("APPDATA").map(PathBuf::from)
+}
+
+#[cfg(not(windows))]
+fn base_data_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_DATA_HOME").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => Some(dir),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")),
+    }
+}
@@ -0,0 +1,492 @@
+use std::fmt::{self, Write};
+use std::ops::Range;
+
+use super::chip8::Chip8;
+use super::commons::parse_number;
+use super::disasm::Syntax;
+use super::instruction::Instruction;
+
+/// Help text of the debugger commands.
+const HELP: &str = "\
+s, step [N]          execute N instructions (default 1)
+n, next              like step, but run called subroutines (2NNN) to completion
+c, continue          resume execution until a breakpoint
+b, break ADDR        break when the program counter reaches ADDR
+b, break op PATTERN  break before opcodes matching PATTERN, e.g. D??F or F?0A (? is any digit)
+w, watch ADDR [LEN]  break after an instruction writes to LEN bytes (default 1) from ADDR
+w, watch read|rw ADDR [LEN]
+                     break after reads, or after any access
+w, watch REG         break after a register (V0..VF or I) changes value
+d, delete [N]        delete breakpoint or watchpoint N (all of them without N)
+breaks               list the breakpoints and watchpoints
+r, regs              show the registers and timers
+stack                show the call stack
+m, mem [ADDR] [LEN]  dump LEN bytes of memory (default 64) from ADDR (default I)
+l, list [ADDR] [N]   disassemble N instructions (default 8) from ADDR (default PC)
+q, quit              exit the emulator
+Numbers are decimal, or hexadecimal with the 0x prefix. Press enter to repeat the last command.";
+
+/// A condition to pause execution.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Breakpoint {
+    /// The program counter reaches an address.
+    Address(u16),
+    /// The next opcode matches a pattern of 4 hex digits, `None` being wildcards.
+    Opcode([Option<u8>; 4]),
+    /// The last instruction read or wrote a memory range.
+    Memory {
+        range: Range<usize>,
+        read: bool,
+        write: bool,
+    },
+    /// The last instruction changed a register, whose value is kept to compare.
+    Register { register: Register, value: u16 },
+}
+
+/// A register watched for changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Register {
+    V(u8),
+    I,
+}
+
+impl Register {
+    fn get(self, chip8: &Chip8) -> u16 {
+        match self {
+            Register::V(x) => chip8.v[x as usize] as u16,
+            Register::I => chip8.i,
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+        }
+    }
+}
+
+impl Breakpoint {
+    /// Check if the breakpoint is hit, returning the details to report (empty if none).
+    ///
+    /// Watchpoints look at the effects of the last instruction, executed at `last_pc`.
+    fn check(&mut self, chip8: &Chip8, last_pc: Option<u16>) -> Option<String> {
+        let by = match last_pc {
+            Some(pc) => format!("by {:#06X}", pc),
+            None => "before the debugger started".to_string(),
+        };
+        match self {
+            Breakpoint::Address(address) => (chip8.pc == *address).then(String::new),
+            Breakpoint::Opcode(pattern) => {
+                let opcode = opcode_at(chip8, chip8.pc)?;
+                pattern
+                    .iter()
+                    .enumerate()
+                    .all(|(k, digit)| {
+                        digit.is_none_or(|digit| (opcode >> (12 - 4 * k)) & 0xF == digit as u16)
+                    })
+                    .then(String::new)
+            }
+            Breakpoint::Memory { range, read, write } => {
+                let accesses = chip8.accesses();
+                let overlaps = |access: &Option<Range<usize>>| {
+                    access
+                        .as_ref()
+                        .filter(|access| access.start < range.end && range.start < access.end)
+                        .map(describe_range)
+                };
+                let written = overlaps(&accesses.write).filter(|_| *write);
+                let read = overlaps(&accesses.read).filter(|_| *read);
+                match (read, written) {
+                    (_, Some(written)) => Some(format!(" ({} written {})", written, by)),
+                    (Some(read), None) => Some(format!(" ({} read {})", read, by)),
+                    (None, None) => None,
+                }
+            }
+            Breakpoint::Register { register, value } => {
+                let old = *value;
+                *value = register.get(chip8);
+                (*value != old).then(|| format!(" ({:#X} -> {:#X} {})", old, value, by))
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Breakpoint::Address(address) => format!("at {:#06X}", address),
+            Breakpoint::Opcode(pattern) => {
+                let pattern: String = pattern
+                    .iter()
+                    .map(|digit| digit.map_or('?', |d| char::from_digit(d as u32, 16).unwrap()))
+                    .collect();
+                format!("on opcode {}", pattern.to_uppercase())
+            }
+            Breakpoint::Memory { range, read, write } => {
+                let accesses = match (read, write) {
+                    (true, true) => "accesses to",
+                    (true, false) => "reads of",
+                    _ => "writes to",
+                };
+                format!("on {} {}", accesses, describe_range(range))
+            }
+            Breakpoint::Register { register, .. } => format!("on changes of {}", register),
+        }
+    }
+}
+
+/// Interactive debugger, independent of the frontend.
+///
+/// The frontend runs frames with `Chip8::run_frame_until` (or `Headless::run_frame_until`),
+/// calling `check` before each instruction, and feeds the lines typed by the user to `command`
+/// while the debugger is paused. The debugger starts paused on the first instruction.
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    paused: bool,
+    /// Pause at the next check.
+    stop_next: bool,
+    /// Instructions left to execute before pausing.
+    steps: Option<u32>,
+    /// Return address and stack depth of a subroutine being stepped over.
+    step_over: Option<(u16, usize)>,
+    /// Address of the last instruction checked, that watchpoints report.
+    last_pc: Option<u16>,
+    /// Reason of the last pause, to report it.
+    message: Option<String>,
+    last_command: String,
+    /// The user asked to exit the emulator.
+    pub quit_requested: bool,
+}
+
+impl Debugger {
+    /// Create a debugger, that pauses before the first instruction.
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            paused: false,
+            stop_next: true,
+            steps: None,
+            step_over: None,
+            last_pc: None,
+            message: None,
+            last_command: String::new(),
+            quit_requested: false,
+        }
+    }
+
+    /// Check if execution is paused, waiting for commands.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Check if there is a breakpoint on an address.
+    pub fn has_breakpoint_at(&self, address: u16) -> bool {
+        self.breakpoints.contains(&Breakpoint::Address(address))
+    }
+
+    /// Pause before the next instruction, e.g. when the user presses a key.
+    pub fn interrupt(&mut self) {
+        self.stop_next = true;
+    }
+
+    /// Check if execution must pause before the next instruction; to be called before each one.
+    pub fn check(&mut self, chip8: &Chip8) -> bool {
+        // every breakpoint is checked, to keep the values of the watched registers up to date
+        let mut breakpoint = None;
+        for (n, b) in self.breakpoints.iter_mut().enumerate() {
+            if let Some(details) = b.check(chip8, self.last_pc) {
+                breakpoint = breakpoint.or(Some((n, details)));
+            }
+        }
+        self.last_pc = Some(chip8.pc);
+        let stepped = match &mut self.steps {
+            Some(steps) => {
+                *steps -= 1;
+                *steps == 0
+            }
+            None => false,
+        };
+        let reason = if let Some((n, details)) = breakpoint {
+            Some(format!(
+                "hit breakpoint {} {}{}\n",
+                n,
+                self.breakpoints[n].describe(),
+                details
+            ))
+        } else if self.stop_next || stepped || self.step_over == Some((chip8.pc, chip8.stack.len()))
+        {
+            Some(String::new())
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => {
+                self.pause(chip8, reason);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Get the report of the last pause (where and why), if not taken yet.
+    pub fn take_message(&mut self) -> Option<String> {
+        self.message.take()
+    }
+
+    /// Execute a command typed by the user, returning its output.
+    pub fn command(&mut self, chip8: &Chip8, line: &str) -> Result<String, String> {
+        let line = match line.trim() {
+            "" => self.last_command.clone(),
+            line => line.to_string(),
+        };
+        self.last_command = line.clone();
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let args: Vec<&str> = words.collect();
+        match (command, args.as_slice()) {
+            ("s" | "step", []) => self.resume(Some(1)),
+            ("s" | "step", [n]) => {
+                let n = n.parse().map_err(|_| format!("invalid count `{}`", n))?;
+                if n == 0 {
+                    return Ok(String::new());
+                }
+                self.resume(Some(n))
+            }
+            ("n" | "next", []) => match chip8.instruction_at(chip8.pc) {
+                Some(Instruction::Call { .. }) => {
+                    self.step_over = Some((chip8.pc.wrapping_add(2), chip8.stack.len()));
+                    self.resume(None)
+                }
+                _ => self.resume(Some(1)),
+            },
+            ("c" | "continue", []) => self.resume(None),
+            ("b" | "break", ["op", pattern]) => {
+                self.add_breakpoint(Breakpoint::Opcode(parse_pattern(pattern)?))
+            }
+            ("b" | "break", [address]) => {
+                self.add_breakpoint(Breakpoint::Address(parse_number(address)?))
+            }
+            ("w" | "watch", [register]) if parse_register(register).is_some() => {
+                let register = parse_register(register).unwrap();
+                let value = register.get(chip8);
+                self.add_breakpoint(Breakpoint::Register { register, value })
+            }
+            ("w" | "watch", args) if !args.is_empty() => {
+                let (read, write, args) = match args {
+                    ["read", args @ ..] => (true, false, args),
+                    ["rw", args @ ..] => (true, true, args),
+                    args => (false, true, args),
+                };
+                let (address, len) = match args {
+                    [address] => (parse_number(address)?, 1),
+                    [address, len] => (parse_number(address)?, parse_number(len)?),
+                    _ => return Err(format!("invalid watchpoint `{}`", line)),
+                };
+                if len == 0 {
+                    return Err("empty watchpoint".to_string());
+                }
+                let start = address as usize;
+                let range = start..start + len as usize;
+                self.add_breakpoint(Breakpoint::Memory { range, read, write })
+            }
+            ("d" | "delete", []) => {
+                self.breakpoints.clear();
+                Ok("deleted all breakpoints\n".to_string())
+            }
+            ("d" | "delete", [n]) => match n.parse::<usize>() {
+                Ok(n) if n < self.breakpoints.len() => {
+                    self.breakpoints.remove(n);
+                    Ok(format!("deleted breakpoint {}\n", n))
+                }
+                _ => Err(format!("no breakpoint `{}`", n)),
+            },
+            ("breaks", []) => Ok(self.list_breakpoints()),
+            ("r" | "regs", []) => Ok(registers(chip8)),
+            ("stack", []) => Ok(stack(chip8)),
+            ("m" | "mem", args) if args.len() <= 2 => {
+                let address = match args.first() {
+                    Some(address) => parse_number(address)?,
+                    None => chip8.i,
+                };
+                let len = match args.get(1) {
+                    Some(len) => parse_number(len)?,
+                    None => 64,
+                };
+                Ok(memory(chip8, address as usize, len as usize))
+            }
+            ("l" | "list", args) if args.len() <= 2 => {
+                let address = match args.first() {
+                    Some(address) => parse_number(address)?,
+                    None => chip8.pc,
+                };
+                let count = match args.get(1) {
+                    Some(count) => parse_number(count)?,
+                    None => 8,
+                };
+                Ok(list(chip8, address, count as usize))
+            }
+            ("q" | "quit", []) => {
+                self.quit_requested = true;
+                Ok(String::new())
+            }
+            ("h" | "help", []) => Ok(format!("{}\n", HELP)),
+            _ => Err(format!("unknown command `{}`, try `help`", line)),
+        }
+    }
+
+    fn pause(&mut self, chip8: &Chip8, reason: String) {
+        self.paused = true;
+        self.stop_next = false;
+        self.steps = None;
+        self.step_over = None;
+        self.message = Some(format!("{}{}", reason, list(chip8, chip8.pc, 1)));
+    }
+
+    fn resume(&mut self, steps: Option<u32>) -> Result<String, String> {
+        self.paused = false;
+        self.steps = steps;
+        Ok(String::new())
+    }
+
+    fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> Result<String, String> {
+        let message = format!(
+            "breakpoint {} {}\n",
+            self.breakpoints.len(),
+            breakpoint.describe()
+        );
+        self.breakpoints.push(breakpoint);
+        Ok(message)
+    }
+
+    fn list_breakpoints(&self) -> String {
+        if self.breakpoints.is_empty() {
+            return "no breakpoints\n".to_string();
+        }
+        self.breakpoints
+            .iter()
+            .enumerate()
+            .map(|(n, b)| format!("{}: {}\n", n, b.describe()))
+            .collect()
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Format the registers and timers.
+pub fn registers(chip8: &Chip8) -> String {
+    let mut out = String::new();
+    for (k, v) in chip8.v.iter().enumerate() {
+        let separator = if k % 8 == 7 { '\n' } else { ' ' };
+        write!(out, "V{:X}={:02X}{}", k, v, separator).unwrap();
+    }
+    writeln!(
+        out,
+        "I={:#06X} PC={:#06X} DT={:02X} ST={:02X}",
+        chip8.i,
+        chip8.pc,
+        chip8.timer.get(),
+        chip8.sound.get()
+    )
+    .unwrap();
+    out
+}
+
+/// Format the call stack, innermost call first.
+pub fn stack(chip8: &Chip8) -> String {
+    if chip8.stack.is_empty() {
+        return "empty stack\n".to_string();
+    }
+    chip8
+        .stack
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(depth, address)| format!("{}: {:#06X}\n", depth, address))
+        .collect()
+}
+
+/// Format `len` bytes of memory from `address`, 16 per line.
+pub fn memory(chip8: &Chip8, address: usize, len: usize) -> String {
+    let end = (address + len).min(chip8.memory.len());
+    let mut out = String::new();
+    for row in (address..end).step_by(16) {
+        write!(out, "{:#06X} ", row).unwrap();
+        for byte in &chip8.memory[row..(row + 16).min(end)] {
+            write!(out, " {:02X}", byte).unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Disassemble `count` instructions from `address`, marking the program counter.
+pub fn list(chip8: &Chip8, address: u16, count: usize) -> String {
+    let mut out = String::new();
+    let mut address = address;
+    for _ in 0..count {
+        let marker = if address == chip8.pc { '>' } else { ' ' };
+        let (size, text) = match chip8.instruction_at(address) {
+            Some(instruction) => (instruction.size(), Syntax::Classic.format(&instruction)),
+            None => match opcode_at(chip8, address) {
+                Some(opcode) => (2, format!("DW {:#06X}", opcode)),
+                None => break,
+            },
+        };
+        let bytes: String = chip8.memory[address as usize..address as usize + size]
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        writeln!(out, "{} {:#06X}  {:<8}  {}", marker, address, bytes, text).unwrap();
+        address = address.wrapping_add(size as u16);
+    }
+    out
+}
+
+fn opcode_at(chip8: &Chip8, address: u16) -> Option<u16> {
+    let address = address as usize;
+    let bytes = chip8.memory.get(address..address + 2)?;
+    Some(((bytes[0] as u16) << 8) | bytes[1] as u16)
+}
+
+/// Parse a register name: `V0`..`VF` or `I`.
+fn parse_register(text: &str) -> Option<Register> {
+    match text.to_uppercase().as_str() {
+        "I" => Some(Register::I),
+        name => {
+            let digit = name.strip_prefix('V')?;
+            match u8::from_str_radix(digit, 16) {
+                Ok(x) if digit.len() == 1 => Some(Register::V(x)),
+                _ => None,
+            }
+        }
+    }
+}
+
+fn describe_range(range: &Range<usize>) -> String {
+    if range.len() == 1 {
+        format!("{:#06X}", range.start)
+    } else {
+        format!("{:#06X}-{:#06X}", range.start, range.end - 1)
+    }
+}
+
+fn parse_pattern(text: &str) -> Result<[Option<u8>; 4], String> {
+    let digits: Vec<Option<u8>> = text
+        .chars()
+        .map(|c| match c {
+            '?' | 'x' | 'X' | '.' => Ok(None),
+            _ => c
+                .to_digit(16)
+                .map(|d| Some(d as u8))
+                .ok_or_else(|| format!("invalid opcode pattern `{}`", text)),
+        })
+        .collect::<Result<_, _>>()?;
+    digits
+        .try_into()
+        .map_err(|_| format!("opcode pattern `{}` must have 4 digits", text))
+}
@@ -0,0 +1,318 @@
+use std::str::FromStr;
+
+use super::instruction::Instruction;
+use super::memory::PROGRAM_START;
+
+/// Mnemonic syntax used to print instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    /// Classic assembly syntax, e.g. `LD V1, 0x20`.
+    Classic,
+    /// Octo syntax, e.g. `v1 := 0x20`.
+    Octo,
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "classic" => Ok(Syntax::Classic),
+            "octo" => Ok(Syntax::Octo),
+            _ => Err(format!(
+                "unknown syntax `{}`, expected `classic` or `octo`",
+                s
+            )),
+        }
+    }
+}
+
+impl Syntax {
+    /// Format an instruction with this syntax.
+    pub fn format(&self, instruction: &Instruction) -> String {
+        match self {
+            Syntax::Classic => classic(instruction),
+            Syntax::Octo => octo(instruction),
+        }
+    }
+}
+
+/// Contents of a disassembled location.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Content {
+    /// An instruction.
+    Code(Instruction),
+    /// A data byte, e.g. a sprite row.
+    Data(u8),
+}
+
+/// A disassembled location.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    /// Address in memory.
+    pub address: u16,
+    /// Raw bytes at the address.
+    pub bytes: Vec<u8>,
+    /// Decoded contents.
+    pub content: Content,
+}
+
+impl Line {
+    /// Format the line as `address  raw bytes  mnemonic`, with a bit pattern for data bytes.
+    pub fn format(&self, syntax: Syntax) -> String {
+        match self.content {
+            Content::Code(instruction) => format!(
+                "{:#05X}  {:<4}  {}",
+                self.address,
+                self.bytes
+                    .iter()
+                    .map(|b| format!("{:02X}", b))
+                    .collect::<String>(),
+                syntax.format(&instruction)
+            ),
+            Content::Data(byte) => {
+                let text = match syntax {
+                    Syntax::Classic => format!("DB {:#04X}", byte),
+                    Syntax::Octo => format!("{:#04X}", byte),
+                };
+                let pattern: String = (0..8)
+                    .map(|b| if byte & (0x80 >> b) != 0 { '#' } else { '.' })
+                    .collect();
+                format!(
+                    "{:#05X}  {:02X}    {:<16}{} {}",
+                    self.address,
+                    byte,
+                    text,
+                    match syntax {
+                        Syntax::Classic => ';',
+                        Syntax::Octo => '#',
+                    },
+                    pattern
+                )
+            }
+        }
+    }
+}
+
+/// Disassemble a ROM loaded at 0x200.
+///
+/// When `trace` is set, the control flow is followed from the entry point (through jumps, calls,
+/// skips and `BNNN` tables of jumps) and only reachable locations are decoded as code, everything else is
+/// reported as data. Otherwise, every pair of bytes that decodes to a valid instruction is code.
+pub fn disassemble(rom: &[u8], trace: bool) -> Vec<Line> {
+    let code = if trace {
+        trace_code(rom)
+    } else {
+        (0..rom.len())
+            .map(|k| k % 2 == 0 && decode_at(rom, k).is_some())
+            .collect()
+    };
+
+    let mut lines = Vec::new();
+    let mut k = 0;
+    while k < rom.len() {
+        let address = (PROGRAM_START + k) as u16;
+        match decode_at(rom, k).filter(|_| code[k]) {
+            Some(instruction) => {
+                let size = instruction.size();
+                lines.push(Line {
+                    address,
+                    bytes: rom[k..k + size].to_vec(),
+                    content: Content::Code(instruction),
+                });
+                k += size;
+            }
+            None => {
+                lines.push(Line {
+                    address,
+                    bytes: vec![rom[k]],
+                    content: Content::Data(rom[k]),
+                });
+                k += 1;
+            }
+        }
+    }
+    lines
+}
+
+/// Follow the control flow from the entry point and mark the ROM offsets where instructions start.
+pub fn trace_code(rom: &[u8]) -> Vec<bool> {
+    let mut code = vec![false; rom.len()];
+    let mut pending = vec![PROGRAM_START as u16];
+    while let Some(address) = pending.pop() {
+        let k = match (address as usize).checked_sub(PROGRAM_START) {
+            Some(k) if k < rom.len() => k,
+            _ => continue,
+        };
+        if code[k] {
+            continue;
+        }
+        let instruction = match decode_at(rom, k) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        code[k] = true;
+        pending.extend(successors(rom, address, &instruction));
+    }
+    code
+}
+
+/// Addresses that can be executed after the instruction at `address`.
+fn successors(rom: &[u8], address: u16, instruction: &Instruction) -> Vec<u16> {
+    let next = address.wrapping_add(instruction.size() as u16);
+    // skips jump over a whole instruction, which may be four bytes long (XO-CHIP)
+    let after_next = (next as usize)
+        .checked_sub(PROGRAM_START)
+        .and_then(|k| decode_at(rom, k))
+        .map_or(2, |i| i.size() as u16);
+    match *instruction {
+        Instruction::Return | Instruction::Exit => vec![],
+        Instruction::Jump { nnn } => vec![nnn],
+        Instruction::JumpOffset { nnn } => jump_table(rom, nnn),
+        Instruction::Call { nnn } => vec![nnn, next],
+        Instruction::SkipEqImm { .. }
+        | Instruction::SkipNeImm { .. }
+        | Instruction::SkipEqReg { .. }
+        | Instruction::SkipNeReg { .. }
+        | Instruction::SkipKey { .. }
+        | Instruction::SkipNotKey { .. } => vec![next, next.wrapping_add(after_next)],
+        _ => vec![next],
+    }
+}
+
+/// Entries of a `BNNN` table: `NNN`, then every following jump at an even offset (the usual
+/// tables of `1NNN` indexed by even values of `V0`), up to an offset of 0xFF.
+fn jump_table(rom: &[u8], nnn: u16) -> Vec<u16> {
+    let mut entries = vec![nnn];
+    for offset in (2..=0xFE).step_by(2) {
+        let address = nnn.wrapping_add(offset);
+        let entry = (address as usize)
+            .checked_sub(PROGRAM_START)
+            .and_then(|k| decode_at(rom, k));
+        match entry {
+            Some(Instruction::Jump { .. }) => entries.push(address),
+            _ => break,
+        }
+    }
+    entries
+}
+
+fn decode_at(rom: &[u8], k: usize) -> Option<Instruction> {
+    let word = |k: usize| Some(((*rom.get(k)? as u16) << 8) | *rom.get(k + 1)? as u16);
+    let opcode = word(k)?;
+    if opcode == 0xF000 {
+        Instruction::decode_long(opcode, word(k + 2)?).ok()
+    } else {
+        Instruction::decode(opcode).ok()
+    }
+}
+
+fn classic(instruction: &Instruction) -> String {
+    match *instruction {
+        Instruction::Clear => "CLS".to_string(),
+        Instruction::Return => "RET".to_string(),
+        Instruction::ScrollDown { n } => format!("SCD {}", n),
+        Instruction::ScrollRight => "SCR".to_string(),
+        Instruction::ScrollLeft => "SCL".to_string(),
+        Instruction::Exit => "EXIT".to_string(),
+        Instruction::LowRes => "LOW".to_string(),
+        Instruction::HighRes => "HIGH".to_string(),
+        Instruction::Sys { nnn } => format!("SYS {:#05X}", nnn),
+        Instruction::Jump { nnn } => format!("JP {:#05X}", nnn),
+        Instruction::Call { nnn } => format!("CALL {:#05X}", nnn),
+        Instruction::SkipEqImm { x, nn } => format!("SE V{:X}, {:#04X}", x, nn),
+        Instruction::SkipNeImm { x, nn } => format!("SNE V{:X}, {:#04X}", x, nn),
+        Instruction::SkipEqReg { x, y } => format!("SE V{:X}, V{:X}", x, y),
+        Instruction::LoadImm { x, nn } => format!("LD V{:X}, {:#04X}", x, nn),
+        Instruction::AddImm { x, nn } => format!("ADD V{:X}, {:#04X}", x, nn),
+        Instruction::Move { x, y } => format!("LD V{:X}, V{:X}", x, y),
+        Instruction::Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+        Instruction::And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+        Instruction::Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+        Instruction::Add { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+        Instruction::Sub { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+        Instruction::ShiftRight { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+        Instruction::SubReversed { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+        Instruction::ShiftLeft { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+        Instruction::SkipNeReg { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+        Instruction::LoadI { nnn } => format!("LD I, {:#05X}", nnn),
+        Instruction::JumpOffset { nnn } => format!("JP V0, {:#05X}", nnn),
+        Instruction::Random { x, nn } => format!("RND V{:X}, {:#04X}", x, nn),
+        Instruction::Draw { x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        Instruction::SkipKey { x } => format!("SKP V{:X}", x),
+        Instruction::SkipNotKey { x } => format!("SKNP V{:X}", x),
+        Instruction::GetDelay { x } => format!("LD V{:X}, DT", x),
+        Instruction::WaitKey { x } => format!("LD V{:X}, K", x),
+        Instruction::SetDelay { x } => format!("LD DT, V{:X}", x),
+        Instruction::SetSound { x } => format!("LD ST, V{:X}", x),
+        Instruction::AddI { x } => format!("ADD I, V{:X}", x),
+        Instruction::Font { x } => format!("LD F, V{:X}", x),
+        Instruction::BigFont { x } => format!("LD HF, V{:X}", x),
+        Instruction::Bcd { x } => format!("LD B, V{:X}", x),
+        Instruction::Store { x } => format!("LD [I], V{:X}", x),
+        Instruction::Load { x } => format!("LD V{:X}, [I]", x),
+        Instruction::SaveFlags { x } => format!("LD R, V{:X}", x),
+        Instruction::LoadFlags { x } => format!("LD V{:X}, R", x),
+        Instruction::StoreRange { x, y } => format!("LD [I], V{:X}-V{:X}", x, y),
+        Instruction::LoadRange { x, y } => format!("LD V{:X}-V{:X}, [I]", x, y),
+        Instruction::LoadILong { nnnn } => format!("LD I, LONG {:#06X}", nnnn),
+        Instruction::Plane { n } => format!("PLANE {}", n),
+        Instruction::Audio => "AUDIO".to_string(),
+        Instruction::Pitch { x } => format!("PITCH V{:X}", x),
+    }
+}
+
+fn octo(instruction: &Instruction) -> String {
+    match *instruction {
+        Instruction::Clear => "clear".to_string(),
+        Instruction::Return => "return".to_string(),
+        Instruction::ScrollDown { n } => format!("scroll-down {}", n),
+        Instruction::ScrollRight => "scroll-right".to_string(),
+        Instruction::ScrollLeft => "scroll-left".to_string(),
+        Instruction::Exit => "exit".to_string(),
+        Instruction::LowRes => "lores".to_string(),
+        Instruction::HighRes => "hires".to_string(),
+        Instruction::Sys { nnn } => format!("{:#04X} {:#04X}", nnn >> 8, nnn & 0xFF),
+        Instruction::Jump { nnn } => format!("jump {:#05X}", nnn),
+        Instruction::Call { nnn } => format!(":call {:#05X}", nnn),
+        Instruction::SkipEqImm { x, nn } => format!("if v{:x} != {:#04X} then", x, nn),
+        Instruction::SkipNeImm { x, nn } => format!("if v{:x} == {:#04X} then", x, nn),
+        Instruction::SkipEqReg { x, y } => format!("if v{:x} != v{:x} then", x, y),
+        Instruction::LoadImm { x, nn } => format!("v{:x} := {:#04X}", x, nn),
+        Instruction::AddImm { x, nn } => format!("v{:x} += {:#04X}", x, nn),
+        Instruction::Move { x, y } => format!("v{:x} := v{:x}", x, y),
+        Instruction::Or { x, y } => format!("v{:x} |= v{:x}", x, y),
+        Instruction::And { x, y } => format!("v{:x} &= v{:x}", x, y),
+        Instruction::Xor { x, y } => format!("v{:x} ^= v{:x}", x, y),
+        Instruction::Add { x, y } => format!("v{:x} += v{:x}", x, y),
+        Instruction::Sub { x, y } => format!("v{:x} -= v{:x}", x, y),
+        Instruction::ShiftRight { x, y } => format!("v{:x} >>= v{:x}", x, y),
+        Instruction::SubReversed { x, y } => format!("v{:x} =- v{:x}", x, y),
+        Instruction::ShiftLeft { x, y } => format!("v{:x} <<= v{:x}", x, y),
+        Instruction::SkipNeReg { x, y } => format!("if v{:x} == v{:x} then", x, y),
+        Instruction::LoadI { nnn } => format!("i := {:#05X}", nnn),
+        Instruction::JumpOffset { nnn } => format!("jump0 {:#05X}", nnn),
+        Instruction::Random { x, nn } => format!("v{:x} := random {:#04X}", x, nn),
+        Instruction::Draw { x, y, n } => format!("sprite v{:x} v{:x} {}", x, y, n),
+        Instruction::SkipKey { x } => format!("if v{:x} -key then", x),
+        Instruction::SkipNotKey { x } => format!("if v{:x} key then", x),
+        Instruction::GetDelay { x } => format!("v{:x} := delay", x),
+        Instruction::WaitKey { x } => format!("v{:x} := key", x),
+        Instruction::SetDelay { x } => format!("delay := v{:x}", x),
+        Instruction::SetSound { x } => format!("buzzer := v{:x}", x),
+        Instruction::AddI { x } => format!("i += v{:x}", x),
+        Instruction::Font { x } => format!("i := hex v{:x}", x),
+        Instruction::BigFont { x } => format!("i := bighex v{:x}", x),
+        Instruction::Bcd { x } => format!("bcd v{:x}", x),
+        Instruction::Store { x } => format!("save v{:x}", x),
+        Instruction::Load { x } => format!("load v{:x}", x),
+        Instruction::SaveFlags { x } => format!("saveflags v{:x}", x),
+        Instruction::LoadFlags { x } => format!("loadflags v{:x}", x),
+        Instruction::StoreRange { x, y } => format!("save v{:x} - v{:x}", x, y),
+        Instruction::LoadRange { x, y } => format!("load v{:x} - v{:x}", x, y),
+        Instruction::LoadILong { nnnn } => format!("i := long {:#06X}", nnnn),
+        Instruction::Plane { n } => format!("plane {}", n),
+        Instruction::Audio => "audio".to_string(),
+        Instruction::Pitch { x } => format!("pitch := v{:x}", x),
+    }
+}
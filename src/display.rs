@@ -3,22 +3,31 @@ use sdl2::rect::Rect;
 use sdl2::render::WindowCanvas;
 use sdl2::Sdl;
 
-use super::commons::CanTick;
+use super::framebuffer::{Framebuffer, HEIGHT, WIDTH};
+
+/// Colors of the four pixel values: blank, first plane, second plane, both planes.
+const PALETTE: [Color; 4] = [
+    Color::RGB(0, 0, 0),
+    Color::RGB(255, 255, 255),
+    Color::RGB(170, 170, 170),
+    Color::RGB(85, 85, 85),
+];
 
 /// Display manager
 pub struct Display {
     canvas: WindowCanvas,
-    memory: [u8; 64 * 32],
-    refresh: bool,
-    pixel_size: u32,
 }
 
 impl Display {
-    /// Create a new display manager.
+    /// Create a new display manager, with a window of 64x32 pixels of the given size.
     pub fn new(sdl: &Sdl, pixel_size: u32) -> Result<Display, String> {
         let video_subsystem = sdl.video()?;
         let window = video_subsystem
-            .window("rchip8", 64 * pixel_size, 32 * pixel_size)
+            .window(
+                "rchip8",
+                WIDTH as u32 * pixel_size,
+                HEIGHT as u32 * pixel_size,
+            )
             .position_centered()
             .opengl()
             .build()
@@ -26,80 +35,38 @@ impl Display {
 
         let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
 
-        Ok(Display {
-            canvas,
-            memory: [0; 64 * 32],
-            refresh: true,
-            pixel_size,
-        })
-    }
-
-    /// Draw a sprite at a given location.
-    #[must_use = "Value must be used to set VF"]
-    pub fn draw(&mut self, x: u8, y: u8, sprite: &[u8]) -> bool {
-        let x = x as usize;
-        let y = y as usize;
-        let mut carry = false;
-        for v in 0..sprite.len() {
-            for u in 0..8 {
-                let idx = (x + u) + (y + v) * 64;
-                if idx >= 64 * 32 {
-                    continue;
-                }
-                let p = (sprite[v] >> (7 - u)) & 1;
-                // check carry
-                if self.memory[idx] == 1 && p ^ self.memory[idx] == 0 {
-                    carry = true;
-                }
-                // set pixel
-                self.memory[idx] ^= p;
-            }
-        }
-        self.refresh = true;
-        carry
-    }
-
-    /// Clear the display.
-    pub fn clear(&mut self) {
-        for i in 0..self.memory.len() {
-            self.memory[i] = 0;
-        }
-        self.refresh = true;
+        Ok(Display { canvas })
     }
-}
 
-/// Each tick, the display manager refreshes the screen, if necessary.
-impl CanTick for Display {
-    fn tick(&mut self) {
-        if !self.refresh {
-            return;
+    /// Draw the contents of a framebuffer on the screen, scaled to the window size.
+    pub fn render(&mut self, framebuffer: &Framebuffer) {
+        let (width, height) = (framebuffer.width() as u32, framebuffer.height() as u32);
+        if self.canvas.logical_size() != (width, height) {
+            self.canvas
+                .set_logical_size(width, height)
+                .unwrap_or_else(|err| {
+                    eprintln!("Unable to set resolution: {}", err);
+                });
         }
 
         // background
-        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.set_draw_color(PALETTE[0]);
         self.canvas.clear();
 
-        // draw memory
-        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
-        for i in 0..64 * 32 {
-            if self.memory[i] == 1 {
-                let x: u32 = (i % 64).try_into().unwrap();
-                let y: u32 = (i / 64).try_into().unwrap();
-                self.canvas
-                    .fill_rect(Rect::new(
-                        (x * self.pixel_size) as i32,
-                        (y * self.pixel_size) as i32,
-                        self.pixel_size,
-                        self.pixel_size,
-                    ))
-                    .unwrap_or_else(|err| {
-                        eprintln!("Unable to draw: {}", err);
-                    });
-            }
+        // draw memory, one color at a time
+        for (color, &rgb) in PALETTE.iter().enumerate().skip(1) {
+            let rects: Vec<Rect> = (0..framebuffer.height())
+                .flat_map(|y| (0..framebuffer.width()).map(move |x| (x, y)))
+                .filter(|&(x, y)| framebuffer.pixel(x, y) as usize == color)
+                .map(|(x, y)| Rect::new(x as i32, y as i32, 1, 1))
+                .collect();
+            self.canvas.set_draw_color(rgb);
+            self.canvas.fill_rects(&rects).unwrap_or_else(|err| {
+                eprintln!("Unable to draw: {}", err);
+            });
         }
 
         // actual draw
         self.canvas.present();
-        self.refresh = false;
     }
 }
@@ -0,0 +1,37 @@
+use std::fs;
+use std::path::PathBuf;
+
+use super::commons;
+
+/// Directory where the RPL user flags are saved, one file per ROM (see `commons::data_dir`).
+pub fn flags_dir() -> Option<PathBuf> {
+    commons::data_dir().map(|dir| dir.join("flags"))
+}
+
+/// Load the flags saved for a ROM, given its hash (see `memory::rom_hash`).
+///
+/// ROMs that never saved their flags get all zeros.
+pub fn load(rom_hash: u64) -> Result<[u8; 16], String> {
+    let mut flags = [0; 16];
+    let path = match flags_path(rom_hash) {
+        Some(path) if path.exists() => path,
+        _ => return Ok(flags),
+    };
+    let data = fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let len = data.len().min(flags.len());
+    flags[..len].copy_from_slice(&data[..len]);
+    Ok(flags)
+}
+
+/// Save the flags of a ROM, given its hash (see `memory::rom_hash`).
+pub fn save(rom_hash: u64, flags: &[u8; 16]) -> Result<(), String> {
+    let path = flags_path(rom_hash).ok_or("cannot find the user data directory")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    fs::write(&path, flags).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn flags_path(rom_hash: u64) -> Option<PathBuf> {
+    flags_dir().map(|dir| dir.join(format!("{:016x}.flags", rom_hash)))
+}
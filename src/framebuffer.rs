@@ -0,0 +1,282 @@
+use std::fmt;
+
+use super::state::{Reader, Snapshot, Writer};
+
+/// Width of the screen in low resolution mode, in pixels.
+pub const WIDTH: usize = 64;
+
+/// Height of the screen in low resolution mode, in pixels.
+pub const HEIGHT: usize = 32;
+
+/// Width of the screen in high resolution mode (SUPER-CHIP), in pixels.
+pub const HIRES_WIDTH: usize = 128;
+
+/// Height of the screen in high resolution mode (SUPER-CHIP), in pixels.
+pub const HIRES_HEIGHT: usize = 64;
+
+/// In-memory framebuffer, independent of any rendering backend.
+///
+/// The framebuffer is either in low (64x32) or high (128x64) resolution mode, and has two
+/// bitplanes (XO-CHIP), so that each pixel has one of four colors. Drawing, clearing and
+/// scrolling only affect the selected planes; by default only the first plane is selected,
+/// which makes the framebuffer monochrome.
+#[derive(Clone)]
+pub struct Framebuffer {
+    memory: [u8; HIRES_WIDTH * HIRES_HEIGHT],
+    hires: bool,
+    planes: u8,
+    refresh: bool,
+}
+
+impl Framebuffer {
+    /// Create a new, blank framebuffer in low resolution mode.
+    pub fn new() -> Framebuffer {
+        Framebuffer {
+            memory: [0; HIRES_WIDTH * HIRES_HEIGHT],
+            hires: false,
+            planes: 1,
+            refresh: true,
+        }
+    }
+
+    /// Width of the screen in the current mode.
+    pub fn width(&self) -> usize {
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            WIDTH
+        }
+    }
+
+    /// Height of the screen in the current mode.
+    pub fn height(&self) -> usize {
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            HEIGHT
+        }
+    }
+
+    /// Check if the framebuffer is in high resolution mode.
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Switch between low and high resolution mode, clearing the screen.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.memory.fill(0);
+        self.refresh = true;
+    }
+
+    /// Bitmask of the selected planes.
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// Select the planes affected by drawing, clearing and scrolling, as a bitmask.
+    pub fn set_planes(&mut self, planes: u8) {
+        self.planes = planes & 0b11;
+    }
+
+    /// Number of selected planes.
+    pub fn plane_count(&self) -> usize {
+        self.planes.count_ones() as usize
+    }
+
+    /// Draw an 8 pixels wide sprite at a given location.
+    ///
+    /// The starting position always wraps around the screen; the parts of the sprite that
+    /// go past the edges are either wrapped to the other side or clipped. When several planes
+    /// are selected, `sprite` contains the data for each of them, one after the other.
+    ///
+    /// Returns the number of rows of the sprite where a lit pixel was turned off, in any plane.
+    #[must_use = "Value must be used to set VF"]
+    pub fn draw(&mut self, x: u8, y: u8, sprite: &[u8], wrap: bool) -> usize {
+        let mut collisions = 0;
+        for (plane, data) in self.plane_chunks(sprite) {
+            let rows = data.iter().map(|&row| (row as u16) << 8);
+            collisions |= self.draw_rows(x, y, rows, 8, plane, wrap);
+        }
+        self.refresh = true;
+        collisions.count_ones() as usize
+    }
+
+    /// Draw a 16x16 sprite (two bytes per row) at a given location, see `draw`.
+    #[must_use = "Value must be used to set VF"]
+    pub fn draw_wide(&mut self, x: u8, y: u8, sprite: &[u8], wrap: bool) -> usize {
+        let mut collisions = 0;
+        for (plane, data) in self.plane_chunks(sprite) {
+            let rows = data
+                .chunks(2)
+                .map(|row| ((row[0] as u16) << 8) | *row.get(1).unwrap_or(&0) as u16);
+            collisions |= self.draw_rows(x, y, rows, 16, plane, wrap);
+        }
+        self.refresh = true;
+        collisions.count_ones() as usize
+    }
+
+    /// Split sprite data between the selected planes.
+    fn plane_chunks<'a>(&self, sprite: &'a [u8]) -> Vec<(u8, &'a [u8])> {
+        let count = self.plane_count();
+        if count == 0 {
+            return Vec::new();
+        }
+        let len = sprite.len() / count;
+        [1u8, 2u8]
+            .into_iter()
+            .filter(|plane| self.planes & plane != 0)
+            .zip(sprite.chunks(len.max(1)))
+            .collect()
+    }
+
+    /// Draw the rows of a sprite in a plane, returning a bitmask of the rows with a collision.
+    fn draw_rows(
+        &mut self,
+        x: u8,
+        y: u8,
+        rows: impl Iterator<Item = u16>,
+        width: usize,
+        plane: u8,
+        wrap: bool,
+    ) -> u32 {
+        let (w, h) = (self.width(), self.height());
+        let x = x as usize % w;
+        let y = y as usize % h;
+        let mut collisions = 0;
+        for (v, row) in rows.enumerate() {
+            for u in 0..width {
+                let (mut px, mut py) = (x + u, y + v);
+                if wrap {
+                    px %= w;
+                    py %= h;
+                } else if px >= w || py >= h {
+                    continue;
+                }
+                let idx = px + py * w;
+                if (row >> (15 - u)) & 1 == 0 {
+                    continue;
+                }
+                // check carry
+                if self.memory[idx] & plane != 0 {
+                    collisions |= 1 << v;
+                }
+                // set pixel
+                self.memory[idx] ^= plane;
+            }
+        }
+        collisions
+    }
+
+    /// Scroll the selected planes down by `n` pixels.
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll(|x, y| (Some(x), y.checked_sub(n)));
+    }
+
+    /// Scroll the selected planes right by `n` pixels.
+    pub fn scroll_right(&mut self, n: usize) {
+        self.scroll(|x, y| (x.checked_sub(n), Some(y)));
+    }
+
+    /// Scroll the selected planes left by `n` pixels.
+    pub fn scroll_left(&mut self, n: usize) {
+        self.scroll(|x, y| (Some(x + n), Some(y)));
+    }
+
+    /// Move the selected planes, `source` giving the origin of each pixel (if on screen).
+    fn scroll(&mut self, source: impl Fn(usize, usize) -> (Option<usize>, Option<usize>)) {
+        let (w, h) = (self.width(), self.height());
+        let old = self.memory;
+        for y in 0..h {
+            for x in 0..w {
+                let moved = match source(x, y) {
+                    (Some(sx), Some(sy)) if sx < w && sy < h => old[sx + sy * w],
+                    _ => 0,
+                };
+                let idx = x + y * w;
+                self.memory[idx] = (old[idx] & !self.planes) | (moved & self.planes);
+            }
+        }
+        self.refresh = true;
+    }
+
+    /// Clear the selected planes.
+    pub fn clear(&mut self) {
+        for pixel in self.memory.iter_mut() {
+            *pixel &= !self.planes;
+        }
+        self.refresh = true;
+    }
+
+    /// Copy a row of pixels from bytes, most significant bit first, to the first plane (for
+    /// memory-mapped displays).
+    pub fn set_row(&mut self, y: usize, bytes: &[u8]) {
+        let width = self.width();
+        let row = &mut self.memory[y * width..(y + 1) * width];
+        for (x, pixel) in row.iter_mut().enumerate().take(bytes.len() * 8) {
+            let bit = (bytes[x / 8] >> (7 - x % 8)) & 1;
+            if *pixel & 1 != bit {
+                *pixel ^= 1;
+                self.refresh = true;
+            }
+        }
+    }
+
+    /// Check if the pixel at a given location (in the current mode) is lit in any plane.
+    pub fn is_set(&self, x: usize, y: usize) -> bool {
+        self.pixel(x, y) != 0
+    }
+
+    /// Color of the pixel at a given location (in the current mode), from 0 to 3.
+    ///
+    /// Bit 0 of the color is the value of the pixel in the first plane, bit 1 in the second.
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.memory[x + y * self.width()]
+    }
+
+    /// Check if the framebuffer changed since the last call, and reset the flag.
+    pub fn take_refresh(&mut self) -> bool {
+        let refresh = self.refresh;
+        self.refresh = false;
+        refresh
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Snapshot for Framebuffer {
+    fn save(&self, w: &mut Writer) {
+        w.bool(self.hires);
+        w.u8(self.planes);
+        w.bytes(&self.memory);
+    }
+
+    fn restore(&mut self, r: &mut Reader) -> Result<(), String> {
+        self.hires = r.bool()?;
+        self.planes = r.u8()? & 0b11;
+        let memory = r.bytes(self.memory.len())?;
+        for (pixel, &value) in self.memory.iter_mut().zip(memory) {
+            *pixel = value & 0b11;
+        }
+        self.refresh = true;
+        Ok(())
+    }
+}
+
+/// Text rendering of the framebuffer, with `.` for blank pixels and `#` for pixels lit in the
+/// first plane; pixels lit in the second plane only or in both are shown as `+` and `@`.
+impl fmt::Display for Framebuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                write!(f, "{}", ['.', '#', '+', '@'][self.pixel(x, y) as usize])?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,414 @@
+use std::fmt::Write as _;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use super::chip8::Chip8;
+
+/// Description of the register file, sent to GDB: `V0`..`VF` (8 bits), `I` and `pc` (16 bits,
+/// little endian) and `sp`, the depth of the call stack.
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.rchip8.chip8">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+/// Number of registers, see `TARGET_XML`.
+const REGISTERS: usize = 19;
+
+/// Signals reported to GDB when execution stops.
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+/// Stub of the GDB Remote Serial Protocol, to debug programs with GDB or other RSP front ends.
+///
+/// Like the built-in debugger, the frontend calls `check` before each instruction (through
+/// `Chip8::run_frame_until`), and `poll` between frames to handle the packets received from GDB,
+/// executing nothing while the stub is paused. Execution pauses before the first instruction.
+pub struct GdbStub {
+    /// Connection with GDB, until it detaches.
+    stream: Option<TcpStream>,
+    /// Bytes received and not handled yet.
+    input: Vec<u8>,
+    no_ack: bool,
+    breakpoints: Vec<u16>,
+    paused: bool,
+    /// Pause at the next check (single-step).
+    stop_next: bool,
+    /// GDB resumed execution and waits for a stop reply.
+    running: bool,
+    /// Signal of the last stop, reported again by `?`.
+    signal: u8,
+    /// GDB killed the program.
+    pub quit_requested: bool,
+}
+
+impl GdbStub {
+    /// Listen on a TCP address and wait for GDB to connect.
+    pub fn listen(address: &str) -> Result<GdbStub, String> {
+        let listener = TcpListener::bind(address).map_err(|e| format!("{}: {}", address, e))?;
+        eprintln!("Waiting for GDB on {}", address);
+        let (stream, peer) = listener
+            .accept()
+            .map_err(|e| format!("{}: {}", address, e))?;
+        eprintln!("GDB connected from {}", peer);
+        stream.set_nodelay(true).unwrap_or(());
+        Ok(GdbStub {
+            stream: Some(stream),
+            input: Vec::new(),
+            no_ack: false,
+            breakpoints: Vec::new(),
+            paused: false,
+            stop_next: true,
+            running: false,
+            signal: SIGTRAP,
+            quit_requested: false,
+        })
+    }
+
+    /// Check if execution is paused, waiting for GDB.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Check if execution must pause before the next instruction; to be called before each one.
+    pub fn check(&mut self, chip8: &Chip8) -> bool {
+        if self.stream.is_none() || !(self.stop_next || self.breakpoints.contains(&chip8.pc)) {
+            return false;
+        }
+        self.stop(SIGTRAP);
+        true
+    }
+
+    /// Handle the packets received from GDB; with `wait`, block while paused until GDB resumes
+    /// execution.
+    pub fn poll(&mut self, chip8: &mut Chip8, wait: bool) {
+        loop {
+            let blocking = wait && self.paused && !self.quit_requested;
+            if !self.receive(blocking) {
+                return;
+            }
+            self.handle_input(chip8);
+            if !blocking {
+                return;
+            }
+        }
+    }
+
+    /// Tell GDB that the program exited, if it is waiting for execution to stop.
+    pub fn finish(&mut self) {
+        if self.running {
+            self.running = false;
+            self.send("W00");
+        }
+    }
+
+    fn stop(&mut self, signal: u8) {
+        self.paused = true;
+        self.stop_next = false;
+        self.signal = signal;
+        if self.running {
+            self.running = false;
+            self.send(&format!("S{:02x}", signal));
+        }
+    }
+
+    fn resume(&mut self, step: bool) {
+        self.paused = false;
+        self.stop_next = step;
+        self.running = true;
+    }
+
+    /// Stop talking to GDB and let the program run.
+    fn disconnect(&mut self) {
+        if self.stream.take().is_some() {
+            eprintln!("GDB disconnected");
+        }
+        self.paused = false;
+        self.running = false;
+    }
+
+    /// Read the available bytes, returning `false` if there were none.
+    fn receive(&mut self, blocking: bool) -> bool {
+        let Some(stream) = &mut self.stream else {
+            return false;
+        };
+        if stream.set_nonblocking(!blocking).is_err() {
+            self.disconnect();
+            return false;
+        }
+        let mut buffer = [0; 4096];
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => {
+                    self.input.extend_from_slice(&buffer[..n]);
+                    return true;
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return false,
+                Err(_) => break,
+            }
+        }
+        self.disconnect();
+        false
+    }
+
+    fn handle_input(&mut self, chip8: &mut Chip8) {
+        while let Some(&first) = self.input.first() {
+            match first {
+                b'$' => {
+                    // wait for the end of the packet and its checksum
+                    let Some(end) = self.input.iter().position(|&b| b == b'#') else {
+                        return;
+                    };
+                    if self.input.len() < end + 3 {
+                        return;
+                    }
+                    let packet: Vec<u8> = self.input.drain(..end + 3).collect();
+                    let data = &packet[1..end];
+                    let checksum = std::str::from_utf8(&packet[end + 1..])
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                    let valid = checksum == Some(data.iter().fold(0u8, |s, &b| s.wrapping_add(b)));
+                    if !self.no_ack {
+                        self.send_raw(if valid { b"+" } else { b"-" });
+                    }
+                    if valid {
+                        let data = String::from_utf8_lossy(data).into_owned();
+                        self.handle_packet(chip8, &data);
+                    }
+                }
+                0x03 => {
+                    // interrupt
+                    self.input.remove(0);
+                    if !self.paused {
+                        self.stop(SIGINT);
+                    }
+                }
+                _ => {
+                    // acknowledgments (retransmissions are not supported) and noise
+                    self.input.remove(0);
+                }
+            }
+        }
+    }
+
+    fn handle_packet(&mut self, chip8: &mut Chip8, packet: &str) {
+        let Some((command, args)) = packet.get(..1).zip(packet.get(1..)) else {
+            self.send("");
+            return;
+        };
+        let reply = match command {
+            "?" => format!("S{:02x}", self.signal),
+            "g" => (0..REGISTERS).map(|n| read_register(chip8, n)).collect(),
+            "G" => match decode_hex(args) {
+                Some(bytes) if write_registers(chip8, &bytes) => "OK".to_string(),
+                _ => "E01".to_string(),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(n) if n < REGISTERS => read_register(chip8, n),
+                _ => "E01".to_string(),
+            },
+            "P" => match args.split_once('=').and_then(|(n, value)| {
+                Some((usize::from_str_radix(n, 16).ok()?, decode_hex(value)?))
+            }) {
+                Some((n, bytes)) if write_register(chip8, n, &bytes) => "OK".to_string(),
+                _ => "E01".to_string(),
+            },
+            "m" => match parse_range(args)
+                .and_then(|(start, len)| chip8.memory.get(start..start.checked_add(len)?))
+            {
+                Some(bytes) => encode_hex(bytes),
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let write = args.split_once(':').and_then(|(range, data)| {
+                    let (start, len) = parse_range(range)?;
+                    let bytes = decode_hex(data).filter(|bytes| bytes.len() == len)?;
+                    chip8
+                        .memory
+                        .get_mut(start..start.checked_add(len)?)
+                        .map(|memory| memory.copy_from_slice(&bytes))
+                });
+                match write {
+                    Some(()) => "OK".to_string(),
+                    None => "E01".to_string(),
+                }
+            }
+            "c" | "s" => {
+                if let Ok(address) = u16::from_str_radix(args, 16) {
+                    chip8.pc = address;
+                }
+                self.resume(command == "s");
+                return;
+            }
+            "Z" | "z" => match parse_breakpoint(args) {
+                Some(address) => {
+                    self.breakpoints.retain(|&b| b != address);
+                    if command == "Z" {
+                        self.breakpoints.push(address);
+                    }
+                    "OK".to_string()
+                }
+                // watchpoints are not supported
+                None => String::new(),
+            },
+            "k" => {
+                self.quit_requested = true;
+                return;
+            }
+            "D" => {
+                self.send("OK");
+                self.disconnect();
+                return;
+            }
+            "H" | "T" => "OK".to_string(),
+            _ => self.handle_query(packet),
+        };
+        self.send(&reply);
+    }
+
+    /// Reply to the general queries, or with an empty packet for the unsupported ones.
+    fn handle_query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+".to_string();
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, len)) = parse_range(range) else {
+                return "E01".to_string();
+            };
+            let data = TARGET_XML.get(offset..).unwrap_or("");
+            return match data.get(..len) {
+                Some(chunk) => format!("m{}", chunk),
+                None => format!("l{}", data),
+            };
+        }
+        match packet {
+            "QStartNoAckMode" => {
+                // the acknowledgment of this packet was already sent
+                self.no_ack = true;
+                "OK".to_string()
+            }
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |s, b| s.wrapping_add(b));
+        let packet = format!("${}#{:02x}", data, checksum);
+        self.send_raw(packet.as_bytes());
+    }
+
+    fn send_raw(&mut self, bytes: &[u8]) {
+        let Some(stream) = &mut self.stream else {
+            return;
+        };
+        // replies are short, write them at once
+        let sent = stream
+            .set_nonblocking(false)
+            .and_then(|_| stream.write_all(bytes));
+        if sent.is_err() {
+            self.disconnect();
+        }
+    }
+}
+
+/// Read a register, encoded as in the `g` packet.
+fn read_register(chip8: &Chip8, n: usize) -> String {
+    match n {
+        0..=15 => format!("{:02x}", chip8.v[n]),
+        16 => encode_hex(&chip8.i.to_le_bytes()),
+        17 => encode_hex(&chip8.pc.to_le_bytes()),
+        _ => format!("{:02x}", chip8.stack.len()),
+    }
+}
+
+/// Write a register, returning `false` if the value is invalid.
+fn write_register(chip8: &mut Chip8, n: usize, bytes: &[u8]) -> bool {
+    match (n, bytes) {
+        (0..=15, &[value]) => chip8.v[n] = value,
+        (16, &[low, high]) => chip8.i = u16::from_le_bytes([low, high]),
+        (17, &[low, high]) => chip8.pc = u16::from_le_bytes([low, high]),
+        // the stack can only be unwound
+        (18, &[depth]) if depth as usize <= chip8.stack.len() => {
+            chip8.stack.truncate(depth as usize)
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Write all the registers at once, as encoded in the `G` packet.
+fn write_registers(chip8: &mut Chip8, bytes: &[u8]) -> bool {
+    // validate the stack depth before writing anything
+    if bytes.len() != 16 + 2 + 2 + 1 || bytes[20] as usize > chip8.stack.len() {
+        return false;
+    }
+    for (n, &value) in bytes[..16].iter().enumerate() {
+        write_register(chip8, n, &[value]);
+    }
+    write_register(chip8, 16, &bytes[16..18])
+        && write_register(chip8, 17, &bytes[18..20])
+        && write_register(chip8, 18, &bytes[20..])
+}
+
+/// Parse the `ADDR,LENGTH` arguments of the memory packets.
+fn parse_range(args: &str) -> Option<(usize, usize)> {
+    let (start, len) = args.split_once(',')?;
+    Some((
+        usize::from_str_radix(start, 16).ok()?,
+        usize::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+/// Parse the `TYPE,ADDR,KIND` arguments of a breakpoint packet, for software and hardware
+/// breakpoints only.
+fn parse_breakpoint(args: &str) -> Option<u16> {
+    let mut fields = args.split(',');
+    match fields.next()? {
+        "0" | "1" => u16::from_str_radix(fields.next()?, 16).ok(),
+        _ => None,
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, b| {
+        write!(out, "{:02x}", b).unwrap();
+        out
+    })
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|k| u8::from_str_radix(text.get(k..k + 2)?, 16).ok())
+        .collect()
+}
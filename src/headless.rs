@@ -0,0 +1,214 @@
+use std::fs;
+
+use super::chip8::{Chip8, KeyInput};
+use super::vip::Vip;
+
+/// A scheduled change of a key state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    /// Frame at which the change happens (before the frame is executed).
+    pub frame: u64,
+    /// Key, from 0x0 to 0xF.
+    pub key: u8,
+    /// New state of the key.
+    pub down: bool,
+}
+
+/// Headless frontend, that does not need a window or an audio device.
+///
+/// The framebuffer stays in the machine memory, keys are driven by a script of `KeyEvent`s,
+/// the frames of a movie (or directly through `Chip8::set_key`), and sound is counted instead
+/// of played.
+pub struct Headless {
+    events: Vec<KeyEvent>,
+    next_event: usize,
+    /// Recorded keys of each frame, replayed before the events.
+    inputs: Vec<KeyInput>,
+    input: KeyInput,
+    /// Keys pressed and released during the same frame, kept down until the next one.
+    pending_release: [bool; 16],
+    /// The last frame was interrupted, its keys are already set.
+    interrupted: bool,
+    frame: u64,
+    playing: bool,
+    beeps: u64,
+    beep_frames: u64,
+}
+
+impl Headless {
+    /// Create a new headless frontend with no scripted input.
+    pub fn new() -> Headless {
+        Headless::with_events(Vec::new())
+    }
+
+    /// Create a new headless frontend that replays the given key events.
+    pub fn with_events(mut events: Vec<KeyEvent>) -> Headless {
+        events.sort_by_key(|e| e.frame);
+        Headless {
+            events,
+            next_event: 0,
+            inputs: Vec::new(),
+            input: KeyInput::default(),
+            pending_release: [false; 16],
+            interrupted: false,
+            frame: 0,
+            playing: false,
+            beeps: 0,
+            beep_frames: 0,
+        }
+    }
+
+    /// Create a new headless frontend that replays the keys recorded in a movie.
+    pub fn with_inputs(inputs: Vec<KeyInput>) -> Headless {
+        Headless {
+            inputs,
+            ..Headless::new()
+        }
+    }
+
+    /// Create a new headless frontend that replays a key script from a file.
+    pub fn from_script(path: &str) -> Result<Headless, String> {
+        let script = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(Headless::with_events(parse_script(&script)?))
+    }
+
+    /// Run one 60Hz frame: apply the scripted key events, execute and count beeps.
+    pub fn run_frame(&mut self, chip8: &mut Chip8) -> Result<(), String> {
+        self.run_frame_until(chip8, |_| false)?;
+        Ok(())
+    }
+
+    /// Run one 60Hz frame, stopping early as with `Chip8::run_frame_until`.
+    ///
+    /// An interrupted frame is not counted, and the next call resumes it.
+    pub fn run_frame_until(
+        &mut self,
+        chip8: &mut Chip8,
+        stop: impl FnMut(&Chip8) -> bool,
+    ) -> Result<bool, String> {
+        if !self.interrupted {
+            chip8.set_input(self.input_at_frame());
+        }
+        self.interrupted = chip8.run_frame_until(stop)?;
+        if self.interrupted {
+            return Ok(true);
+        }
+        self.end_frame(chip8.sound_active());
+        Ok(false)
+    }
+
+    /// Run one 60Hz frame of a COSMAC VIP, see `run_frame`.
+    pub fn run_vip_frame(&mut self, vip: &mut Vip) {
+        vip.set_keys(self.input_at_frame().down);
+        vip.run_frame();
+        self.end_frame(vip.sound_active());
+    }
+
+    /// Keys of the last frame.
+    pub fn input(&self) -> KeyInput {
+        self.input
+    }
+
+    /// Apply the recorded keys or the scripted key events of the current frame.
+    fn input_at_frame(&mut self) -> KeyInput {
+        if let Some(&input) = self.inputs.get(self.frame as usize) {
+            self.input = input;
+            return input;
+        }
+        for k in 0..16 {
+            if self.pending_release[k] {
+                self.input.down[k] = false;
+            }
+        }
+        self.pending_release = [false; 16];
+        self.input.pressed = [false; 16];
+        self.input.released = [false; 16];
+        while let Some(event) = self.events.get(self.next_event) {
+            if event.frame > self.frame {
+                break;
+            }
+            let k = event.key as usize;
+            if event.down {
+                self.input.pressed[k] |= !self.input.down[k];
+                self.input.down[k] = true;
+                self.pending_release[k] = false;
+            } else if self.input.down[k] {
+                // like on a keyboard, a key tapped within a frame is still seen down
+                self.input.released[k] = true;
+                if self.input.pressed[k] {
+                    self.pending_release[k] = true;
+                } else {
+                    self.input.down[k] = false;
+                }
+            }
+            self.next_event += 1;
+        }
+        self.input
+    }
+
+    /// Count the beeps and move to the next frame.
+    fn end_frame(&mut self, active: bool) {
+        if active {
+            self.beep_frames += 1;
+            if !self.playing {
+                self.beeps += 1;
+            }
+        }
+        self.playing = active;
+        self.frame += 1;
+    }
+
+    /// Number of frames executed so far.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Number of distinct beeps played so far.
+    pub fn beeps(&self) -> u64 {
+        self.beeps
+    }
+
+    /// Number of frames during which a beep was playing.
+    pub fn beep_frames(&self) -> u64 {
+        self.beep_frames
+    }
+}
+
+impl Default for Headless {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parse a key script.
+///
+/// Each line contains a frame number, a hexadecimal key and either `down` or `up`,
+/// e.g. `120 A down`. Empty lines and everything after a `#` are ignored.
+pub fn parse_script(script: &str) -> Result<Vec<KeyEvent>, String> {
+    let mut events = Vec::new();
+    for (n, line) in script.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |msg: &str| format!("key script line {}: {}", n + 1, msg);
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 3 {
+            return Err(error("expected `<frame> <key> down|up`"));
+        }
+        let frame = fields[0]
+            .parse::<u64>()
+            .map_err(|_| error("invalid frame number"))?;
+        let key = u8::from_str_radix(fields[1], 16)
+            .ok()
+            .filter(|&k| k < 16)
+            .ok_or_else(|| error("invalid key, expected 0-F"))?;
+        let down = match fields[2] {
+            "down" => true,
+            "up" => false,
+            _ => return Err(error("invalid state, expected `down` or `up`")),
+        };
+        events.push(KeyEvent { frame, key, down });
+    }
+    Ok(events)
+}
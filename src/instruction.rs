@@ -0,0 +1,291 @@
+use std::error::Error;
+use std::fmt;
+
+/// A decoded CHIP-8 instruction.
+///
+/// `x` and `y` are register indices (0x0 to 0xF), `n`, `nn` and `nnn` are the 4, 8 and 12 bits
+/// immediate values of the opcode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// `00E0`: clear the screen.
+    Clear,
+    /// `00EE`: return from a subroutine.
+    Return,
+    /// `00CN`: scroll the screen down by `N` pixels (SUPER-CHIP).
+    ScrollDown { n: u8 },
+    /// `00FB`: scroll the screen right by 4 pixels (SUPER-CHIP).
+    ScrollRight,
+    /// `00FC`: scroll the screen left by 4 pixels (SUPER-CHIP).
+    ScrollLeft,
+    /// `00FD`: exit the interpreter (SUPER-CHIP).
+    Exit,
+    /// `00FE`: switch to the 64x32 low resolution mode (SUPER-CHIP).
+    LowRes,
+    /// `00FF`: switch to the 128x64 high resolution mode (SUPER-CHIP).
+    HighRes,
+    /// `0NNN`: call a machine code routine.
+    Sys { nnn: u16 },
+    /// `1NNN`: jump to `NNN`.
+    Jump { nnn: u16 },
+    /// `2NNN`: call the subroutine at `NNN`.
+    Call { nnn: u16 },
+    /// `3XNN`: skip the next instruction if `Vx == NN`.
+    SkipEqImm { x: u8, nn: u8 },
+    /// `4XNN`: skip the next instruction if `Vx != NN`.
+    SkipNeImm { x: u8, nn: u8 },
+    /// `5XY0`: skip the next instruction if `Vx == Vy`.
+    SkipEqReg { x: u8, y: u8 },
+    /// `6XNN`: `Vx = NN`.
+    LoadImm { x: u8, nn: u8 },
+    /// `7XNN`: `Vx += NN`, without carry.
+    AddImm { x: u8, nn: u8 },
+    /// `8XY0`: `Vx = Vy`.
+    Move { x: u8, y: u8 },
+    /// `8XY1`: `Vx |= Vy`.
+    Or { x: u8, y: u8 },
+    /// `8XY2`: `Vx &= Vy`.
+    And { x: u8, y: u8 },
+    /// `8XY3`: `Vx ^= Vy`.
+    Xor { x: u8, y: u8 },
+    /// `8XY4`: `Vx += Vy`, `VF` is the carry.
+    Add { x: u8, y: u8 },
+    /// `8XY5`: `Vx -= Vy`, `VF` is the inverted borrow.
+    Sub { x: u8, y: u8 },
+    /// `8XY6`: `Vx >>= 1`, `VF` is the shifted out bit.
+    ShiftRight { x: u8, y: u8 },
+    /// `8XY7`: `Vx = Vy - Vx`, `VF` is the inverted borrow.
+    SubReversed { x: u8, y: u8 },
+    /// `8XYE`: `Vx <<= 1`, `VF` is the shifted out bit.
+    ShiftLeft { x: u8, y: u8 },
+    /// `5XY2`: store `Vx`..`Vy` in memory starting at `I`, leaving `I` unchanged (XO-CHIP).
+    StoreRange { x: u8, y: u8 },
+    /// `5XY3`: load `Vx`..`Vy` from memory starting at `I`, leaving `I` unchanged (XO-CHIP).
+    LoadRange { x: u8, y: u8 },
+    /// `9XY0`: skip the next instruction if `Vx != Vy`.
+    SkipNeReg { x: u8, y: u8 },
+    /// `ANNN`: `I = NNN`.
+    LoadI { nnn: u16 },
+    /// `BNNN`: jump to `NNN + V0`.
+    JumpOffset { nnn: u16 },
+    /// `CXNN`: `Vx = rand() & NN`.
+    Random { x: u8, nn: u8 },
+    /// `DXYN`: draw the `N` bytes sprite at `I` in position (`Vx`, `Vy`), `VF` is the collision.
+    /// With `N = 0`, draw a 16x16 sprite (SUPER-CHIP).
+    Draw { x: u8, y: u8, n: u8 },
+    /// `F000 NNNN`: `I = NNNN`, a four bytes instruction (XO-CHIP).
+    LoadILong { nnnn: u16 },
+    /// `FN01`: select the drawing planes with the bitmask `N` (XO-CHIP).
+    Plane { n: u8 },
+    /// `F002`: load the 16 bytes audio pattern at `I` (XO-CHIP).
+    Audio,
+    /// `EX9E`: skip the next instruction if key `Vx` is down.
+    SkipKey { x: u8 },
+    /// `EXA1`: skip the next instruction if key `Vx` is up.
+    SkipNotKey { x: u8 },
+    /// `FX07`: `Vx = delay timer`.
+    GetDelay { x: u8 },
+    /// `FX0A`: wait for a key and store it in `Vx`.
+    WaitKey { x: u8 },
+    /// `FX15`: `delay timer = Vx`.
+    SetDelay { x: u8 },
+    /// `FX18`: `sound timer = Vx`.
+    SetSound { x: u8 },
+    /// `FX1E`: `I += Vx`.
+    AddI { x: u8 },
+    /// `FX29`: point `I` to the font sprite of digit `Vx`.
+    Font { x: u8 },
+    /// `FX30`: point `I` to the large font sprite of digit `Vx` (SUPER-CHIP).
+    BigFont { x: u8 },
+    /// `FX3A`: set the audio pattern playback pitch to `Vx` (XO-CHIP).
+    Pitch { x: u8 },
+    /// `FX33`: store the BCD representation of `Vx` at `I`, `I + 1` and `I + 2`.
+    Bcd { x: u8 },
+    /// `FX55`: store `V0`..`Vx` in memory starting at `I`.
+    Store { x: u8 },
+    /// `FX65`: load `V0`..`Vx` from memory starting at `I`.
+    Load { x: u8 },
+    /// `FX75`: store `V0`..`Vx` in the persistent RPL user flags (SUPER-CHIP).
+    SaveFlags { x: u8 },
+    /// `FX85`: load `V0`..`Vx` from the persistent RPL user flags (SUPER-CHIP).
+    LoadFlags { x: u8 },
+}
+
+/// Error returned when an opcode does not correspond to any instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    /// The invalid opcode.
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "instruction {:#06X} not implemented!", self.opcode)
+    }
+}
+
+impl Error for DecodeError {}
+
+impl Instruction {
+    /// Decode an instruction that may span two words, given the opcode and the word after it.
+    pub fn decode_long(opcode: u16, next: u16) -> Result<Instruction, DecodeError> {
+        if opcode == 0xF000 {
+            return Ok(Instruction::LoadILong { nnnn: next });
+        }
+        Instruction::decode(opcode)
+    }
+
+    /// Decode an opcode.
+    ///
+    /// `F000` is the first half of a four bytes instruction, and can only be decoded with
+    /// `decode_long`.
+    pub fn decode(opcode: u16) -> Result<Instruction, DecodeError> {
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let n = (opcode & 0x000F) as u8;
+        let nn = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+        let instruction = match opcode & 0xF000 {
+            0x0000 => match opcode {
+                0x00E0 => Instruction::Clear,
+                0x00EE => Instruction::Return,
+                0x00C0..=0x00CF => Instruction::ScrollDown { n },
+                0x00FB => Instruction::ScrollRight,
+                0x00FC => Instruction::ScrollLeft,
+                0x00FD => Instruction::Exit,
+                0x00FE => Instruction::LowRes,
+                0x00FF => Instruction::HighRes,
+                _ => Instruction::Sys { nnn },
+            },
+            0x1000 => Instruction::Jump { nnn },
+            0x2000 => Instruction::Call { nnn },
+            0x3000 => Instruction::SkipEqImm { x, nn },
+            0x4000 => Instruction::SkipNeImm { x, nn },
+            0x5000 => match n {
+                0x0 => Instruction::SkipEqReg { x, y },
+                0x2 => Instruction::StoreRange { x, y },
+                0x3 => Instruction::LoadRange { x, y },
+                _ => return Err(DecodeError { opcode }),
+            },
+            0x6000 => Instruction::LoadImm { x, nn },
+            0x7000 => Instruction::AddImm { x, nn },
+            0x8000 => match n {
+                0x0 => Instruction::Move { x, y },
+                0x1 => Instruction::Or { x, y },
+                0x2 => Instruction::And { x, y },
+                0x3 => Instruction::Xor { x, y },
+                0x4 => Instruction::Add { x, y },
+                0x5 => Instruction::Sub { x, y },
+                0x6 => Instruction::ShiftRight { x, y },
+                0x7 => Instruction::SubReversed { x, y },
+                0xE => Instruction::ShiftLeft { x, y },
+                _ => return Err(DecodeError { opcode }),
+            },
+            0x9000 if n == 0 => Instruction::SkipNeReg { x, y },
+            0xA000 => Instruction::LoadI { nnn },
+            0xB000 => Instruction::JumpOffset { nnn },
+            0xC000 => Instruction::Random { x, nn },
+            0xD000 => Instruction::Draw { x, y, n },
+            0xE000 => match nn {
+                0x9E => Instruction::SkipKey { x },
+                0xA1 => Instruction::SkipNotKey { x },
+                _ => return Err(DecodeError { opcode }),
+            },
+            0xF000 => match nn {
+                0x01 => Instruction::Plane { n: x },
+                0x02 if x == 0 => Instruction::Audio,
+                0x07 => Instruction::GetDelay { x },
+                0x0A => Instruction::WaitKey { x },
+                0x15 => Instruction::SetDelay { x },
+                0x18 => Instruction::SetSound { x },
+                0x1E => Instruction::AddI { x },
+                0x29 => Instruction::Font { x },
+                0x30 => Instruction::BigFont { x },
+                0x3A => Instruction::Pitch { x },
+                0x33 => Instruction::Bcd { x },
+                0x55 => Instruction::Store { x },
+                0x65 => Instruction::Load { x },
+                0x75 => Instruction::SaveFlags { x },
+                0x85 => Instruction::LoadFlags { x },
+                _ => return Err(DecodeError { opcode }),
+            },
+            _ => return Err(DecodeError { opcode }),
+        };
+        Ok(instruction)
+    }
+
+    /// Size of the instruction in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LoadILong { .. } => 4,
+            _ => 2,
+        }
+    }
+
+    /// Encode the instruction into bytes, as they are stored in memory.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.encode().to_be_bytes().to_vec();
+        if let Instruction::LoadILong { nnnn } = *self {
+            bytes.extend(nnnn.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Encode the instruction back into its opcode (the first word, for `LoadILong`).
+    pub fn encode(&self) -> u16 {
+        let xy =
+            |op: u16, x: u8, y: u8, n: u16| op | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4 | n;
+        let xnn = |op: u16, x: u8, nn: u8| op | (x as u16 & 0xF) << 8 | nn as u16;
+        match *self {
+            Instruction::Clear => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollDown { n } => 0x00C0 | (n as u16 & 0xF),
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::Sys { nnn } => nnn & 0x0FFF,
+            Instruction::Jump { nnn } => 0x1000 | (nnn & 0x0FFF),
+            Instruction::Call { nnn } => 0x2000 | (nnn & 0x0FFF),
+            Instruction::SkipEqImm { x, nn } => xnn(0x3000, x, nn),
+            Instruction::SkipNeImm { x, nn } => xnn(0x4000, x, nn),
+            Instruction::SkipEqReg { x, y } => xy(0x5000, x, y, 0x0),
+            Instruction::LoadImm { x, nn } => xnn(0x6000, x, nn),
+            Instruction::AddImm { x, nn } => xnn(0x7000, x, nn),
+            Instruction::Move { x, y } => xy(0x8000, x, y, 0x0),
+            Instruction::Or { x, y } => xy(0x8000, x, y, 0x1),
+            Instruction::And { x, y } => xy(0x8000, x, y, 0x2),
+            Instruction::Xor { x, y } => xy(0x8000, x, y, 0x3),
+            Instruction::Add { x, y } => xy(0x8000, x, y, 0x4),
+            Instruction::Sub { x, y } => xy(0x8000, x, y, 0x5),
+            Instruction::ShiftRight { x, y } => xy(0x8000, x, y, 0x6),
+            Instruction::SubReversed { x, y } => xy(0x8000, x, y, 0x7),
+            Instruction::ShiftLeft { x, y } => xy(0x8000, x, y, 0xE),
+            Instruction::StoreRange { x, y } => xy(0x5000, x, y, 0x2),
+            Instruction::LoadRange { x, y } => xy(0x5000, x, y, 0x3),
+            Instruction::SkipNeReg { x, y } => xy(0x9000, x, y, 0x0),
+            Instruction::LoadI { nnn } => 0xA000 | (nnn & 0x0FFF),
+            Instruction::JumpOffset { nnn } => 0xB000 | (nnn & 0x0FFF),
+            Instruction::Random { x, nn } => xnn(0xC000, x, nn),
+            Instruction::Draw { x, y, n } => xy(0xD000, x, y, n as u16 & 0xF),
+            Instruction::LoadILong { .. } => 0xF000,
+            Instruction::Plane { n } => xnn(0xF000, n, 0x01),
+            Instruction::Audio => 0xF002,
+            Instruction::SkipKey { x } => xnn(0xE000, x, 0x9E),
+            Instruction::SkipNotKey { x } => xnn(0xE000, x, 0xA1),
+            Instruction::GetDelay { x } => xnn(0xF000, x, 0x07),
+            Instruction::WaitKey { x } => xnn(0xF000, x, 0x0A),
+            Instruction::SetDelay { x } => xnn(0xF000, x, 0x15),
+            Instruction::SetSound { x } => xnn(0xF000, x, 0x18),
+            Instruction::AddI { x } => xnn(0xF000, x, 0x1E),
+            Instruction::Font { x } => xnn(0xF000, x, 0x29),
+            Instruction::BigFont { x } => xnn(0xF000, x, 0x30),
+            Instruction::Pitch { x } => xnn(0xF000, x, 0x3A),
+            Instruction::Bcd { x } => xnn(0xF000, x, 0x33),
+            Instruction::Store { x } => xnn(0xF000, x, 0x55),
+            Instruction::Load { x } => xnn(0xF000, x, 0x65),
+            Instruction::SaveFlags { x } => xnn(0xF000, x, 0x75),
+            Instruction::LoadFlags { x } => xnn(0xF000, x, 0x85),
+        }
+    }
+}
@@ -5,13 +5,36 @@ use sdl2::keyboard::Keycode;
 use sdl2::EventPump;
 use sdl2::Sdl;
 
+use super::chip8::KeyInput;
 use super::commons::CanTick;
 
+/// Emulator commands bound to keys outside of the CHIP-8 keypad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hotkey {
+    /// F5: save the state to the current slot.
+    SaveState,
+    /// F9: load the state from the current slot.
+    LoadState,
+    /// F6: select the previous slot.
+    PreviousSlot,
+    /// F7: select the next slot.
+    NextSlot,
+}
+
 /// Keyboard manager
 pub struct Keyboard {
     event_pump: EventPump,
     pub quit_requested: bool,
+    /// The rewind key (Backspace) is held down.
+    pub rewind_held: bool,
     keys: [bool; 16],
+    /// Keys pressed since the last tick.
+    pressed: [bool; 16],
+    /// Keys released since the last tick.
+    released: [bool; 16],
+    /// Keys pressed and released during the same tick, kept down until the next one.
+    pending_release: [bool; 16],
+    hotkeys: Vec<Hotkey>,
     key_map: HashMap<Keycode, usize>,
 }
 
@@ -42,7 +65,12 @@ impl Keyboard {
         Ok(Keyboard {
             event_pump,
             quit_requested: false,
+            rewind_held: false,
             keys: [false; 16],
+            pressed: [false; 16],
+            released: [false; 16],
+            pending_release: [false; 16],
+            hotkeys: Vec::new(),
             key_map,
         })
     }
@@ -51,28 +79,100 @@ impl Keyboard {
     pub fn is_down(&self, k: u8) -> bool {
         self.keys[k as usize]
     }
+
+    /// Get the state of all the 16 keys.
+    pub fn keys(&self) -> [bool; 16] {
+        self.keys
+    }
+
+    /// Get the keys pressed during the last tick.
+    pub fn pressed(&self) -> [bool; 16] {
+        self.pressed
+    }
+
+    /// Get the keys released during the last tick.
+    pub fn released(&self) -> [bool; 16] {
+        self.released
+    }
+
+    /// Get the keys of the next frame.
+    pub fn input(&self) -> KeyInput {
+        KeyInput {
+            down: self.keys,
+            pressed: self.pressed,
+            released: self.released,
+        }
+    }
+
+    fn key_down(&mut self, i: usize) {
+        self.pressed[i] |= !self.keys[i];
+        self.keys[i] = true;
+        self.pending_release[i] = false;
+    }
+
+    fn key_up(&mut self, i: usize) {
+        if !self.keys[i] {
+            return;
+        }
+        self.released[i] = true;
+        // a key tapped within a tick must still be seen down by the machine
+        if self.pressed[i] {
+            self.pending_release[i] = true;
+        } else {
+            self.keys[i] = false;
+        }
+    }
+
+    /// Get the hotkeys pressed since the last call.
+    pub fn take_hotkeys(&mut self) -> Vec<Hotkey> {
+        std::mem::take(&mut self.hotkeys)
+    }
 }
 
-/// Each tick, the keyboard manager check for keypresses and store their values.
+/// Each tick, the keyboard manager check for keypresses and store their values; a key pressed
+/// and released within a tick is reported down until the next one.
 impl CanTick for Keyboard {
+    #[allow(clippy::single_match)]
     fn tick(&mut self) {
-        for event in self.event_pump.poll_iter() {
+        for i in 0..16 {
+            if self.pending_release[i] {
+                self.keys[i] = false;
+                self.pending_release[i] = false;
+            }
+        }
+        self.pressed = [false; 16];
+        self.released = [false; 16];
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        for event in events {
             match event {
                 Event::Quit { .. }
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => self.quit_requested = true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => self.rewind_held = true,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => self.rewind_held = false,
+                Event::KeyDown {
+                    keycode: Some(k),
+                    repeat: false,
+                    ..
+                } if hotkey(k).is_some() => self.hotkeys.extend(hotkey(k)),
                 Event::KeyDown {
                     keycode: Some(k), ..
                 } => match self.key_map.get(&k) {
-                    Some(i) => self.keys[*i] = true,
+                    Some(&i) => self.key_down(i),
                     None => (),
                 },
                 Event::KeyUp {
                     keycode: Some(k), ..
                 } => match self.key_map.get(&k) {
-                    Some(i) => self.keys[*i] = false,
+                    Some(&i) => self.key_up(i),
                     None => (),
                 },
                 _ => (),
@@ -80,3 +180,13 @@ impl CanTick for Keyboard {
         }
     }
 }
+
+fn hotkey(k: Keycode) -> Option<Hotkey> {
+    match k {
+        Keycode::F5 => Some(Hotkey::SaveState),
+        Keycode::F9 => Some(Hotkey::LoadState),
+        Keycode::F6 => Some(Hotkey::PreviousSlot),
+        Keycode::F7 => Some(Hotkey::NextSlot),
+        _ => None,
+    }
+}
@@ -1,6 +1,33 @@
+pub mod asm;
+#[cfg(feature = "sdl")]
 pub mod audio;
+pub mod cdp1802;
+pub mod chip8;
 pub mod commons;
+pub mod debugger;
+pub mod disasm;
+#[cfg(feature = "sdl")]
 pub mod display;
+pub mod flags;
+pub mod framebuffer;
+pub mod gdb;
+pub mod headless;
+pub mod instruction;
+#[cfg(feature = "sdl")]
 pub mod keyboard;
 pub mod memory;
+pub mod movie;
+pub mod platform;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod scheduler;
+pub mod state;
 pub mod timer;
+pub mod timing;
+pub mod trace;
+#[cfg(feature = "terminal")]
+pub mod tty;
+#[cfg(feature = "terminal")]
+pub mod tui;
+pub mod vip;
@@ -19,21 +19,67 @@ const CHIP8_FONTSET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+/// Large 8x10 font of SUPER-CHIP (digits A-F are from XO-CHIP).
+const SCHIP_BIG_FONTSET: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/// Address of the small font.
+pub const FONT_START: usize = 0x000;
+
+/// Address of the large font.
+pub const BIG_FONT_START: usize = FONT_START + CHIP8_FONTSET.len();
+
+/// Address where programs are loaded.
+pub const PROGRAM_START: usize = 0x200;
+
 /// Load rom from a file into memory.
 pub fn load_rom(mem: &mut [u8], rom_path: &str) -> Result<(), String> {
     let rom = match fs::read(rom_path) {
         Ok(rom_data) => rom_data,
         Err(e) => return Err(e.to_string()),
     };
-    for k in 0..rom.len() {
-        mem[k + 512] = rom[k];
+    load_program(mem, &rom)
+}
+
+/// Load a program from a byte slice into memory.
+pub fn load_program(mem: &mut [u8], rom: &[u8]) -> Result<(), String> {
+    if rom.len() > mem.len() - PROGRAM_START {
+        return Err(format!(
+            "ROM is too large ({} bytes, at most {} allowed)",
+            rom.len(),
+            mem.len() - PROGRAM_START
+        ));
     }
+    mem[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(rom);
     Ok(())
 }
 
-/// Load character set into memory.
+/// Load character sets (small and large) into memory.
 pub fn load_character_set(mem: &mut [u8]) {
-    for k in 0..CHIP8_FONTSET.len() {
-        mem[k] = CHIP8_FONTSET[k];
-    }
+    mem[FONT_START..BIG_FONT_START].copy_from_slice(&CHIP8_FONTSET);
+    mem[BIG_FONT_START..BIG_FONT_START + SCHIP_BIG_FONTSET.len()]
+        .copy_from_slice(&SCHIP_BIG_FONTSET);
+}
+
+/// Hash of a ROM (64 bits FNV-1a), used to identify it in the files saved to disk.
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
 }
@@ -0,0 +1,182 @@
+use std::fs;
+
+use super::chip8::{Chip8, KeyInput};
+use super::platform::Platform;
+use super::quirks::Quirks;
+use super::rng;
+use super::state::{Reader, Writer};
+use super::timing::Timing;
+
+/// Magic bytes at the start of every movie.
+const MAGIC: &[u8; 4] = b"RC8M";
+
+/// Version of the movie format, bumped whenever the layout or the behavior of the replayed
+/// machine changes, since older movies would desync instead of being rejected.
+pub const VERSION: u16 = 7;
+
+/// Recording of a run: the settings of the machine and the keys of each frame.
+///
+/// Since the machine is deterministic given its settings, the seed of its random generator and
+/// its inputs, replaying a movie reproduces the recorded run exactly.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    /// Hash of the ROM (see `memory::rom_hash`).
+    pub rom_hash: u64,
+    /// Emulated platform.
+    pub platform: Platform,
+    /// CPU frequency, in Hz.
+    pub freq: u32,
+    /// Quirks of the machine.
+    pub quirks: Quirks,
+    /// Timing model of the machine.
+    pub timing: Timing,
+    /// Name of the random generator used by `CXNN` (see `rng::GENERATORS`).
+    pub rng: String,
+    /// Seed of the random generator.
+    pub seed: u64,
+    /// RPL user flags at the start of the run.
+    pub flags: [u8; 16],
+    /// Keys of each frame, with their presses and releases so that taps are replayed.
+    pub frames: Vec<KeyInput>,
+}
+
+impl Movie {
+    /// Start recording a machine, whose random generator was seeded with `seed`.
+    pub fn new(chip8: &Chip8, rom_hash: u64, seed: u64) -> Movie {
+        Movie {
+            rom_hash,
+            platform: chip8.platform(),
+            freq: chip8.freq(),
+            quirks: chip8.quirks,
+            timing: chip8.timing,
+            rng: chip8.rng().name().to_string(),
+            seed,
+            flags: chip8.flags,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Create a machine with the settings of the movie, ready to replay it once the ROM is loaded.
+    pub fn machine(&self) -> Result<Chip8, String> {
+        let mut chip8 = Chip8::with_platform(self.platform, self.freq);
+        chip8.quirks = self.quirks;
+        chip8.timing = self.timing;
+        chip8.flags = self.flags;
+        chip8.set_rng(rng::from_name(&self.rng, self.seed)?);
+        Ok(chip8)
+    }
+
+    /// Record the keys of a frame.
+    pub fn push(&mut self, input: KeyInput) {
+        self.frames.push(input);
+    }
+
+    /// Keys of a given frame, `None` after the end of the movie.
+    pub fn keys(&self, frame: usize) -> Option<KeyInput> {
+        self.frames.get(frame).copied()
+    }
+
+    /// Serialize the movie.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(MAGIC);
+        w.u16(VERSION);
+        w.u64(self.rom_hash);
+        let settings = format!(
+            "{} {} rng={} timing={} {}",
+            self.platform, self.freq, self.rng, self.timing, self.quirks
+        );
+        w.u16(settings.len() as u16);
+        w.bytes(settings.as_bytes());
+        w.u64(self.seed);
+        w.bytes(&self.flags);
+        w.u32(self.frames.len() as u32);
+        for input in &self.frames {
+            w.u16(mask(&input.down));
+            w.u16(mask(&input.pressed));
+            w.u16(mask(&input.released));
+        }
+        w.finish()
+    }
+
+    /// Deserialize a movie saved by `to_bytes`.
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, String> {
+        let mut r = Reader::new(data);
+        if r.bytes(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err("not a movie".to_string());
+        }
+        let version = r.u16()?;
+        if version != VERSION {
+            return Err(format!(
+                "movie format version {} is not supported (expected {})",
+                version, VERSION
+            ));
+        }
+        let rom_hash = r.u64()?;
+        let len = r.u16()? as usize;
+        let settings = std::str::from_utf8(r.bytes(len)?).map_err(|e| e.to_string())?;
+        let mut settings = settings.split(' ');
+        let platform = settings.next().unwrap_or_default().parse()?;
+        let freq = settings
+            .next()
+            .and_then(|freq| freq.parse().ok())
+            .ok_or("invalid frequency in movie")?;
+        let mut quirks = Quirks::default();
+        let mut rng = "splitmix".to_string();
+        let mut timing = Timing::Fixed;
+        for spec in settings {
+            if let Some(name) = spec.strip_prefix("rng=") {
+                rng = name.to_string();
+            } else if let Some(name) = spec.strip_prefix("timing=") {
+                timing = name.parse()?;
+            } else {
+                quirks.set(spec)?;
+            }
+        }
+        let seed = r.u64()?;
+        let flags = r.bytes(16)?.try_into().unwrap();
+        let count = r.u32()?;
+        let frames = (0..count)
+            .map(|_| {
+                Ok(KeyInput {
+                    down: keys(r.u16()?),
+                    pressed: keys(r.u16()?),
+                    released: keys(r.u16()?),
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        r.finish()?;
+        Ok(Movie {
+            rom_hash,
+            platform,
+            freq,
+            quirks,
+            timing,
+            rng,
+            seed,
+            flags,
+            frames,
+        })
+    }
+
+    /// Save the movie to a file.
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_bytes()).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Load a movie from a file.
+    pub fn load(path: &str) -> Result<Movie, String> {
+        let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        Movie::from_bytes(&data).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+/// Pack keys into a bitmask, bit `K` is set when key `K` is.
+fn mask(keys: &[bool; 16]) -> u16 {
+    (0..16).fold(0, |mask, k| mask | (keys[k] as u16) << k)
+}
+
+/// Unpack keys from a bitmask.
+fn keys(mask: u16) -> [bool; 16] {
+    std::array::from_fn(|k| mask & (1 << k) != 0)
+}
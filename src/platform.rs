@@ -0,0 +1,95 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::instruction::Instruction;
+use super::quirks::Quirks;
+
+/// CHIP-8 variant emulated by the machine, which determines the available instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    /// Original CHIP-8.
+    Chip8,
+    /// SUPER-CHIP 1.1: high resolution mode, scrolling, 16x16 sprites and large font.
+    SuperChip,
+    /// XO-CHIP: SUPER-CHIP plus 64KB of memory, two bitplanes and audio patterns.
+    XoChip,
+}
+
+impl Platform {
+    /// Quirks to use when none are chosen explicitly.
+    pub fn default_quirks(&self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::default(),
+            Platform::SuperChip => Quirks::SCHIP,
+            Platform::XoChip => Quirks::XOCHIP,
+        }
+    }
+
+    /// Number of RPL user flags available to `FX75`/`FX85`.
+    pub fn flag_count(&self) -> usize {
+        match self {
+            Platform::Chip8 => 0,
+            Platform::SuperChip => 8,
+            Platform::XoChip => 16,
+        }
+    }
+
+    /// Size of the addressable memory, in bytes.
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Platform::XoChip => 0x10000,
+            _ => 0x1000,
+        }
+    }
+
+    /// Check if an instruction is available on this platform.
+    pub fn supports(&self, instruction: &Instruction) -> bool {
+        match instruction {
+            Instruction::ScrollDown { .. }
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Exit
+            | Instruction::LowRes
+            | Instruction::HighRes
+            | Instruction::BigFont { .. } => *self != Platform::Chip8,
+            Instruction::StoreRange { .. }
+            | Instruction::LoadRange { .. }
+            | Instruction::LoadILong { .. }
+            | Instruction::Plane { .. }
+            | Instruction::Audio
+            | Instruction::Pitch { .. } => *self == Platform::XoChip,
+            Instruction::SaveFlags { x } | Instruction::LoadFlags { x } => {
+                (*x as usize) < self.flag_count()
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Name of the platform, as accepted by `from_str`.
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Platform::Chip8 => "chip8",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chip8" => Ok(Platform::Chip8),
+            "schip" => Ok(Platform::SuperChip),
+            "xochip" => Ok(Platform::XoChip),
+            _ => Err(format!(
+                "unknown platform `{}`, expected `chip8`, `schip` or `xochip`",
+                s
+            )),
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Effect of `FX55`/`FX65` on the index register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// `I` is left unchanged.
+    Unchanged,
+    /// `I` is incremented by `X` (CHIP-48).
+    ByX,
+    /// `I` is incremented by `X + 1`, i.e. it points after the last byte (COSMAC VIP).
+    ByXPlusOne,
+}
+
+/// Behavior of the instructions that differ between CHIP-8 interpreters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8XY6`/`8XYE` shift `Vy` and store the result in `Vx`, instead of shifting `Vx` in place.
+    pub shift_vy: bool,
+    /// Effect of `FX55`/`FX65` on `I`.
+    pub memory: IndexIncrement,
+    /// `BNNN` jumps to `NNN + Vx` (`X` being the highest nibble of `NNN`) instead of `NNN + V0`.
+    pub jump_vx: bool,
+    /// `8XY1`, `8XY2` and `8XY3` reset `VF` to 0.
+    pub vf_reset: bool,
+    /// Sprites wrap around the edges of the screen instead of being clipped.
+    pub wrap: bool,
+    /// `DXYN` waits for the vertical blank, ending the frame, so that at most one sprite is
+    /// drawn per frame (always the case with `Timing::Vip`).
+    pub display_wait: bool,
+    /// `FX0A` returns as soon as any key is down, instead of waiting for a key to be pressed
+    /// and released (early versions of this emulator).
+    pub wait_key_down: bool,
+    /// In high resolution, `DXYN` sets `VF` to the number of rows that collided or were clipped
+    /// at the bottom of the screen, instead of 1 on any collision (SUPER-CHIP 1.1).
+    pub collision_rows: bool,
+    /// In low resolution, `DXY0` draws an 8x16 sprite instead of a 16x16 one (SUPER-CHIP 1.1).
+    pub lores_tall_sprite: bool,
+}
+
+/// Names of the presets accepted by `Quirks::from_str`.
+pub const PRESETS: [&str; 4] = ["vip", "chip48", "schip", "xochip"];
+
+impl Quirks {
+    /// Original COSMAC VIP interpreter.
+    pub const VIP: Quirks = Quirks {
+        shift_vy: true,
+        memory: IndexIncrement::ByXPlusOne,
+        jump_vx: false,
+        vf_reset: true,
+        wrap: false,
+        display_wait: true,
+        wait_key_down: false,
+        collision_rows: false,
+        lores_tall_sprite: false,
+    };
+
+    /// CHIP-48 interpreter for the HP-48.
+    pub const CHIP48: Quirks = Quirks {
+        shift_vy: false,
+        memory: IndexIncrement::ByX,
+        jump_vx: true,
+        vf_reset: false,
+        wrap: false,
+        display_wait: false,
+        wait_key_down: false,
+        collision_rows: false,
+        lores_tall_sprite: false,
+    };
+
+    /// SUPER-CHIP 1.1 interpreter for the HP-48.
+    pub const SCHIP: Quirks = Quirks {
+        shift_vy: false,
+        memory: IndexIncrement::Unchanged,
+        jump_vx: true,
+        vf_reset: false,
+        wrap: false,
+        display_wait: false,
+        wait_key_down: false,
+        collision_rows: true,
+        lores_tall_sprite: true,
+    };
+
+    /// XO-CHIP, as implemented by Octo.
+    pub const XOCHIP: Quirks = Quirks {
+        shift_vy: true,
+        memory: IndexIncrement::ByXPlusOne,
+        jump_vx: false,
+        vf_reset: false,
+        wrap: true,
+        display_wait: false,
+        wait_key_down: false,
+        collision_rows: false,
+        lores_tall_sprite: false,
+    };
+
+    /// Override a single quirk, given as `name=value`.
+    ///
+    /// The accepted quirks are `shift=vx|vy`, `memory=unchanged|x|x+1`, `jump=v0|vx`,
+    /// `vf-reset=on|off`, `wrap=on|off`, `vblank=on|off`,
+    /// `key-wait=release|down`, `collision=any|rows` and `lores-dxy0=16x16|8x16`.
+    pub fn set(&mut self, spec: &str) -> Result<(), String> {
+        let (name, value) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("invalid quirk `{}`, expected `name=value`", spec))?;
+        let invalid = || format!("invalid value `{}` for quirk `{}`", value, name);
+        match name {
+            "shift" => {
+                self.shift_vy = match value {
+                    "vx" => false,
+                    "vy" => true,
+                    _ => return Err(invalid()),
+                }
+            }
+            "memory" => {
+                self.memory = match value {
+                    "unchanged" => IndexIncrement::Unchanged,
+                    "x" => IndexIncrement::ByX,
+                    "x+1" => IndexIncrement::ByXPlusOne,
+                    _ => return Err(invalid()),
+                }
+            }
+            "jump" => {
+                self.jump_vx = match value {
+                    "v0" => false,
+                    "vx" => true,
+                    _ => return Err(invalid()),
+                }
+            }
+            "vf-reset" => self.vf_reset = parse_switch(value).ok_or_else(invalid)?,
+            "wrap" => self.wrap = parse_switch(value).ok_or_else(invalid)?,
+            "vblank" => self.display_wait = parse_switch(value).ok_or_else(invalid)?,
+            "key-wait" => {
+                self.wait_key_down = match value {
+                    "release" => false,
+                    "down" => true,
+                    _ => return Err(invalid()),
+                }
+            }
+            "collision" => {
+                self.collision_rows = match value {
+                    "any" => false,
+                    "rows" => true,
+                    _ => return Err(invalid()),
+                }
+            }
+            "lores-dxy0" => {
+                self.lores_tall_sprite = match value {
+                    "16x16" => false,
+                    "8x16" => true,
+                    _ => return Err(invalid()),
+                }
+            }
+            _ => return Err(format!("unknown quirk `{}`", name)),
+        }
+        Ok(())
+    }
+}
+
+/// Default quirks, matching most modern interpreters and ROMs.
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            shift_vy: false,
+            memory: IndexIncrement::Unchanged,
+            jump_vx: false,
+            vf_reset: false,
+            wrap: false,
+            display_wait: false,
+            wait_key_down: false,
+            collision_rows: false,
+            lores_tall_sprite: false,
+        }
+    }
+}
+
+/// Parse a preset name (see `PRESETS`).
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vip" => Ok(Quirks::VIP),
+            "chip48" => Ok(Quirks::CHIP48),
+            "schip" => Ok(Quirks::SCHIP),
+            "xochip" => Ok(Quirks::XOCHIP),
+            _ => Err(format!(
+                "unknown preset `{}`, expected one of: {}",
+                s,
+                PRESETS.join(", ")
+            )),
+        }
+    }
+}
+
+/// All the quirks as space separated `name=value` pairs, as accepted by `Quirks::set`.
+impl fmt::Display for Quirks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let switch = |on: bool| if on { "on" } else { "off" };
+        write!(
+            f,
+            "shift={} memory={} jump={} vf-reset={} wrap={} vblank={} key-wait={} collision={} lores-dxy0={}",
+            if self.shift_vy { "vy" } else { "vx" },
+            match self.memory {
+                IndexIncrement::Unchanged => "unchanged",
+                IndexIncrement::ByX => "x",
+                IndexIncrement::ByXPlusOne => "x+1",
+            },
+            if self.jump_vx { "vx" } else { "v0" },
+            switch(self.vf_reset),
+            switch(self.wrap),
+            switch(self.display_wait),
+            if self.wait_key_down { "down" } else { "release" },
+            if self.collision_rows { "rows" } else { "any" },
+            if self.lores_tall_sprite { "8x16" } else { "16x16" }
+        )
+    }
+}
+
+fn parse_switch(value: &str) -> Option<bool> {
+    match value {
+        "on" | "true" | "1" => Some(true),
+        "off" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
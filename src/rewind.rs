@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+
+use super::chip8::Chip8;
+
+/// A machine snapshot, without the memory.
+struct Frame {
+    /// The machine, with an empty memory.
+    machine: Chip8,
+    /// Bytes changed since the previous frame, with their previous value.
+    undo: Vec<(usize, u8)>,
+}
+
+/// Ring buffer of the last frames of the machine, to step backwards through them.
+///
+/// To keep the snapshots compact, memory is stored once and each frame only records the bytes
+/// it changed.
+pub struct Rewind {
+    frames: VecDeque<Frame>,
+    capacity: usize,
+    memory: Vec<u8>,
+}
+
+impl Rewind {
+    /// Create a rewind buffer holding at most `capacity` frames.
+    pub fn new(capacity: usize) -> Rewind {
+        Rewind {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+            memory: Vec::new(),
+        }
+    }
+
+    /// Record the state of the machine at the end of a frame.
+    ///
+    /// The memory is moved out of the machine while it is cloned, and put back afterwards.
+    pub fn push(&mut self, chip8: &mut Chip8) {
+        if self.capacity == 0 {
+            return;
+        }
+        let undo = if self.memory.len() == chip8.memory.len() {
+            let mut undo = Vec::new();
+            for (address, (old, &new)) in self.memory.iter_mut().zip(&chip8.memory).enumerate() {
+                if *old != new {
+                    undo.push((address, *old));
+                    *old = new;
+                }
+            }
+            undo
+        } else {
+            self.memory = chip8.memory.clone();
+            Vec::new()
+        };
+        if self.frames.len() == self.capacity {
+            // the oldest frame has nothing before it to undo to
+            self.frames.pop_front();
+            if let Some(oldest) = self.frames.front_mut() {
+                oldest.undo.clear();
+            }
+        }
+        let memory = std::mem::take(&mut chip8.memory);
+        let machine = chip8.clone();
+        chip8.memory = memory;
+        self.frames.push_back(Frame { machine, undo });
+    }
+
+    /// Restore the machine to the frame before the last recorded one, and forget the latter.
+    ///
+    /// Returns `false`, leaving the machine untouched, when there is no older frame.
+    pub fn step_back(&mut self, chip8: &mut Chip8) -> bool {
+        if self.frames.len() < 2 {
+            return false;
+        }
+        let last = self.frames.pop_back().unwrap();
+        for (address, value) in last.undo {
+            self.memory[address] = value;
+        }
+        let previous = self.frames.back().unwrap();
+        *chip8 = previous.machine.clone();
+        chip8.memory = self.memory.clone();
+        true
+    }
+}
@@ -0,0 +1,149 @@
+use super::state::{Reader, Snapshot, Writer};
+
+/// Names of the generators accepted by `from_name`.
+pub const GENERATORS: [&str; 2] = ["splitmix", "vip"];
+
+/// Source of the random numbers used by `CXNN`.
+///
+/// Generators are owned by the machine and are part of its snapshots, so that runs can be
+/// reproduced from a seed.
+pub trait Rng: Snapshot {
+    /// Name of the generator, as accepted by `from_name`.
+    fn name(&self) -> &'static str;
+
+    /// Reset the generator with a seed.
+    fn seed(&mut self, seed: u64);
+
+    /// Generate a random byte; `memory` is the machine memory, for generators that read it.
+    fn next_u8(&mut self, memory: &[u8]) -> u8;
+
+    /// Called after every instruction, for generators that depend on timing.
+    fn tick(&mut self) {}
+
+    /// Clone the generator, with its state.
+    fn box_clone(&self) -> Box<dyn Rng>;
+}
+
+impl Clone for Box<dyn Rng> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// Create a generator from its name (see `GENERATORS`), with a seed.
+pub fn from_name(name: &str, seed: u64) -> Result<Box<dyn Rng>, String> {
+    let mut rng: Box<dyn Rng> = match name {
+        "splitmix" => Box::new(SplitMix64::default()),
+        "vip" => Box::new(VipRng::default()),
+        _ => {
+            return Err(format!(
+                "unknown random generator `{}`, expected one of: {}",
+                name,
+                GENERATORS.join(", ")
+            ))
+        }
+    };
+    rng.seed(seed);
+    Ok(rng)
+}
+
+/// SplitMix64, a fast generator with good statistical properties; the default one.
+#[derive(Clone, Debug, Default)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl Rng for SplitMix64 {
+    fn name(&self) -> &'static str {
+        "splitmix"
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.state = seed;
+    }
+
+    fn next_u8(&mut self, _memory: &[u8]) -> u8 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        ((z ^ (z >> 31)) >> 56) as u8
+    }
+
+    fn box_clone(&self) -> Box<dyn Rng> {
+        Box::new(self.clone())
+    }
+}
+
+impl Snapshot for SplitMix64 {
+    fn save(&self, w: &mut Writer) {
+        w.u8(0);
+        w.u64(self.state);
+    }
+
+    fn restore(&mut self, r: &mut Reader) -> Result<(), String> {
+        check_tag(r, 0)?;
+        self.state = r.u64()?;
+        Ok(())
+    }
+}
+
+/// The generator of the COSMAC VIP interpreter.
+///
+/// The VIP keeps a 16 bits value in the R9 register of its CPU, whose low byte is incremented
+/// by the interpreter loop. `CXNN` reads the byte at the address given by the low byte (on the
+/// VIP, somewhere in the interpreter code), adds the high byte to it, and stores the result as
+/// the new high byte. The quality of the numbers depends on the contents of the first page of
+/// memory and on the timing of the program, which some ROMs rely on.
+#[derive(Clone, Debug, Default)]
+pub struct VipRng {
+    low: u8,
+    high: u8,
+}
+
+impl Rng for VipRng {
+    fn name(&self) -> &'static str {
+        "vip"
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.low = seed as u8;
+        self.high = (seed >> 8) as u8;
+    }
+
+    fn next_u8(&mut self, memory: &[u8]) -> u8 {
+        self.high = memory[self.low as usize].wrapping_add(self.high);
+        self.high
+    }
+
+    fn tick(&mut self) {
+        self.low = self.low.wrapping_add(1);
+    }
+
+    fn box_clone(&self) -> Box<dyn Rng> {
+        Box::new(self.clone())
+    }
+}
+
+impl Snapshot for VipRng {
+    fn save(&self, w: &mut Writer) {
+        w.u8(1);
+        w.u8(self.low);
+        w.u8(self.high);
+    }
+
+    fn restore(&mut self, r: &mut Reader) -> Result<(), String> {
+        check_tag(r, 1)?;
+        self.low = r.u8()?;
+        self.high = r.u8()?;
+        Ok(())
+    }
+}
+
+/// Check the generator saved in a snapshot, since each one has its own state.
+fn check_tag(r: &mut Reader, tag: u8) -> Result<(), String> {
+    if r.u8()? != tag {
+        return Err("save state uses a different random generator".to_string());
+    }
+    Ok(())
+}
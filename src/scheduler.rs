@@ -0,0 +1,48 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Frames per second of the emulation, which is also the rate of the timers.
+pub const FRAME_RATE: u32 = 60;
+
+/// Frames the emulation can fall behind before the schedule is restarted.
+const MAX_LAG: u32 = 3;
+
+/// Paces the emulation at 60 frames per second against the wall clock.
+///
+/// Frame `n` is due at `start + n / 60s`, instead of sleeping for a frame duration after each
+/// frame: the time lost when a sleep overshoots is caught up on the following frames, so the
+/// emulation does not drift. When it falls too far behind (e.g. when the process was suspended),
+/// the schedule restarts from the current time rather than running the late frames back to back.
+pub struct Scheduler {
+    start: Instant,
+    frames: u32,
+}
+
+impl Scheduler {
+    /// Start the schedule, with the first frame due now.
+    pub fn new() -> Scheduler {
+        Scheduler {
+            start: Instant::now(),
+            frames: 0,
+        }
+    }
+
+    /// Wait until the next frame is due.
+    pub fn wait(&mut self) {
+        self.frames += 1;
+        let deadline = self.start + Duration::from_secs(self.frames as u64) / FRAME_RATE;
+        let now = Instant::now();
+        if now < deadline {
+            thread::sleep(deadline - now);
+        } else if now - deadline > Duration::from_secs(MAX_LAG as u64) / FRAME_RATE {
+            self.start = now;
+            self.frames = 0;
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
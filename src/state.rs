@@ -0,0 +1,180 @@
+use std::fs;
+use std::path::PathBuf;
+
+use super::chip8::Chip8;
+use super::commons;
+
+/// Magic bytes at the start of every save state.
+const MAGIC: &[u8; 4] = b"RC8S";
+
+/// Version of the save state format, bumped whenever the layout changes.
+pub const VERSION: u16 = 6;
+
+/// Number of save state slots available from the hotkeys.
+pub const SLOTS: u8 = 10;
+
+/// Components whose state can be saved and restored.
+pub trait Snapshot {
+    /// Append the state to a writer.
+    fn save(&self, w: &mut Writer);
+
+    /// Restore the state from a reader; on error, the component may be partially restored.
+    fn restore(&mut self, r: &mut Reader) -> Result<(), String>;
+}
+
+/// Binary serializer for save states (big endian).
+#[derive(Default)]
+pub struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    /// Write a byte.
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    /// Write a 16 bits word.
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend(value.to_be_bytes());
+    }
+
+    /// Write a 32 bits word.
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend(value.to_be_bytes());
+    }
+
+    /// Write a 64 bits word.
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend(value.to_be_bytes());
+    }
+
+    /// Write a boolean, as a byte.
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    /// Write raw bytes.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// Get the serialized data.
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Binary deserializer for save states, see `Writer`.
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Create a reader over serialized data.
+    pub fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data }
+    }
+
+    /// Read a byte.
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// Read a 16 bits word.
+    pub fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    /// Read a 32 bits word.
+    pub fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    /// Read a 64 bits word.
+    pub fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_be_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    /// Read a boolean.
+    pub fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+
+    /// Read `len` raw bytes.
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < len {
+            return Err("save state is truncated".to_string());
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    /// Check that all the data was read.
+    pub fn finish(self) -> Result<(), String> {
+        if !self.data.is_empty() {
+            return Err("save state has trailing data".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Snapshot the whole machine, for the ROM with the given hash (see `memory::rom_hash`).
+pub fn save(chip8: &Chip8, rom_hash: u64) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.bytes(MAGIC);
+    w.u16(VERSION);
+    w.u64(rom_hash);
+    chip8.save(&mut w);
+    w.finish()
+}
+
+/// Restore a snapshot made by `save`.
+///
+/// States from another ROM or another version of the format are rejected, leaving the machine
+/// untouched.
+pub fn load(chip8: &mut Chip8, data: &[u8], rom_hash: u64) -> Result<(), String> {
+    let mut r = Reader::new(data);
+    if r.bytes(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+        return Err("not a save state".to_string());
+    }
+    let version = r.u16()?;
+    if version != VERSION {
+        return Err(format!(
+            "save state format version {} is not supported (expected {})",
+            version, VERSION
+        ));
+    }
+    let hash = r.u64()?;
+    if hash != rom_hash {
+        return Err(format!(
+            "save state is for a different ROM (hash {:016x}, expected {:016x})",
+            hash, rom_hash
+        ));
+    }
+    let mut restored = chip8.clone();
+    restored.restore(&mut r)?;
+    r.finish()?;
+    *chip8 = restored;
+    Ok(())
+}
+
+/// Save the machine to a file.
+pub fn save_file(chip8: &Chip8, rom_hash: u64, path: &str) -> Result<(), String> {
+    fs::write(path, save(chip8, rom_hash)).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Load the machine from a file saved by `save_file`.
+pub fn load_file(chip8: &mut Chip8, rom_hash: u64, path: &str) -> Result<(), String> {
+    let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    load(chip8, &data, rom_hash).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// File of a numbered save state slot for a ROM (see `commons::data_dir`).
+pub fn slot_path(rom_hash: u64, slot: u8) -> Option<PathBuf> {
+    commons::data_dir().map(|dir| {
+        dir.join("states")
+            .join(format!("{:016x}.{}.state", rom_hash, slot))
+    })
+}
@@ -1,26 +1,21 @@
 use super::commons::CanTick;
+use super::state::{Reader, Snapshot, Writer};
 
-/// Delay timer
+/// Delay timer, counting down at 60Hz.
+#[derive(Clone, Default)]
 pub struct Timer {
     time: u8,
-    ticks: u32,
-    freq: u32,
 }
 
 impl Timer {
     /// Create a new delay timer.
-    pub fn new(freq: u32) -> Timer {
-        Timer {
-            time: 0,
-            ticks: 0,
-            freq,
-        }
+    pub fn new() -> Timer {
+        Timer { time: 0 }
     }
 
     // Set the timer to a given duration (at 60Hz).
     pub fn set(&mut self, duration: u8) {
         self.time = duration;
-        self.ticks = duration as u32 * self.freq / 60;
     }
 
     // Get the value of the timer.
@@ -29,12 +24,20 @@ impl Timer {
     }
 }
 
-/// Each tick, the timer is decreased by the appropriate amount, taking into account the different frequencies.
+/// The timer is ticked once per 60Hz frame, independently of the CPU frequency.
 impl CanTick for Timer {
     fn tick(&mut self) {
-        if self.time > 0 {
-            self.ticks -= 1;
-            self.time = (self.ticks * 60 / self.freq) as u8;
-        }
+        self.time = self.time.saturating_sub(1);
+    }
+}
+
+impl Snapshot for Timer {
+    fn save(&self, w: &mut Writer) {
+        w.u8(self.time);
+    }
+
+    fn restore(&mut self, r: &mut Reader) -> Result<(), String> {
+        self.time = r.u8()?;
+        Ok(())
     }
 }
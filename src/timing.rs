@@ -0,0 +1,127 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::instruction::Instruction;
+
+/// Machine cycles of the COSMAC VIP in a 60Hz frame: its CDP1802 runs at 1.76064MHz, with 8
+/// clock cycles per machine cycle.
+pub const VIP_FRAME_CYCLES: u32 = 3668;
+
+/// Machine cycles of each frame taken by the 1861 video chip, which reads the 128 display lines
+/// by DMA, and by the interrupt routine that sets it up and decrements the timers.
+pub const VIP_DISPLAY_CYCLES: u32 = 1024 + 46;
+
+/// Timing model deciding how many instructions run in a frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Timing {
+    /// Every instruction takes the same time, `freq / 60` of them running in each frame.
+    #[default]
+    Fixed,
+    /// Each instruction takes as many machine cycles as with the original COSMAC VIP
+    /// interpreter (see `vip_cycles`), ignoring the CPU frequency. `DXYN` waits for the next
+    /// vertical interrupt, i.e. the end of the frame.
+    Vip,
+}
+
+/// Name of the timing model, as accepted by `from_str`.
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Timing::Fixed => "fixed",
+            Timing::Vip => "vip",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Timing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(Timing::Fixed),
+            "vip" => Ok(Timing::Vip),
+            _ => Err(format!("unknown timing `{}`, expected `fixed` or `vip`", s)),
+        }
+    }
+}
+
+/// Machine cycles taken by an instruction of the COSMAC VIP interpreter, including its fetch
+/// and decode.
+///
+/// `v` and `i` hold the registers before the instruction, and `skipped` tells if a conditional
+/// skip was taken. The costs are counted from the CDP1802 code of the interpreter, 2 machine
+/// cycles per instruction, as disassembled by Laurence Scotford in "Chip-8 on the COSMAC VIP"
+/// (laurencescotford.net):
+///
+/// - fetching, decoding and returning to the fetch loop take 68 cycles, or 40 for the machine
+///   code routines called by `0NNN`, which include `00E0` and `00EE`;
+/// - all the `8XYN` operations but `8XY0` run the same routine, which copies `DF` to `VF`;
+/// - `DXYN` shifts every row of the sprite by `X % 8` bits (20 cycles per bit and row), then
+///   draws the rows that are not clipped at the bottom, skipping the second byte of a row in
+///   the last column of the screen. A collision costs 4 more cycles per byte, which is not
+///   counted, nor is the wait for the vertical interrupt;
+/// - `FX33` subtracts powers of ten, 16 cycles each, and `FX55`/`FX65` copy each register in
+///   14 cycles.
+///
+/// `FX0A` runs a keypad routine of the monitor and is charged like `FX07` each time it is
+/// executed, and `0NNN` is charged for the call alone. The instructions the VIP does not have
+/// are charged like `6XNN`.
+pub fn vip_cycles(instruction: &Instruction, v: &[u8; 16], i: u16, skipped: bool) -> u32 {
+    let skip = if skipped { 4 } else { 0 };
+    match *instruction {
+        Instruction::Clear => 3118,
+        Instruction::Return => 50,
+        Instruction::Sys { .. } => 42,
+        Instruction::Jump { .. } => 80,
+        Instruction::Call { .. } => 94,
+        Instruction::JumpOffset { nnn } => {
+            let carry = (nnn & 0xFF) + v[0] as u16 > 0xFF;
+            90 + 2 * carry as u32
+        }
+        Instruction::SkipEqImm { .. } | Instruction::SkipNeImm { .. } => 78 + skip,
+        Instruction::SkipEqReg { .. } | Instruction::SkipNeReg { .. } => 82 + skip,
+        Instruction::SkipKey { .. } | Instruction::SkipNotKey { .. } => 82 + skip,
+        Instruction::LoadImm { .. } => 74,
+        Instruction::AddImm { .. } => 78,
+        Instruction::Move { .. } => 80,
+        Instruction::Or { .. }
+        | Instruction::And { .. }
+        | Instruction::Xor { .. }
+        | Instruction::Add { .. }
+        | Instruction::Sub { .. }
+        | Instruction::ShiftRight { .. }
+        | Instruction::SubReversed { .. }
+        | Instruction::ShiftLeft { .. } => 112,
+        Instruction::LoadI { .. } => 80,
+        Instruction::Random { .. } => 104,
+        Instruction::Draw { x, y, n } => {
+            let (x, y, rows) = (
+                v[x as usize] as u32 % 64,
+                v[y as usize] as u32 % 32,
+                n as u32,
+            );
+            let drawn = rows.min(32 - y);
+            let row = if x >= 56 { 34 } else { 50 };
+            // the loop ends 4 cycles earlier when it reaches the bottom of the screen
+            let bottom = if y + rows >= 32 { 4 } else { 0 };
+            162 + rows * (46 + 20 * (x % 8)) + drawn * row - bottom
+        }
+        Instruction::GetDelay { .. }
+        | Instruction::SetDelay { .. }
+        | Instruction::SetSound { .. }
+        | Instruction::WaitKey { .. } => 78,
+        Instruction::AddI { x } => {
+            let carry = (i & 0xFF) + v[x as usize] as u16 > 0xFF;
+            84 + 6 * carry as u32
+        }
+        Instruction::Font { .. } => 88,
+        Instruction::Bcd { x } => {
+            let value = v[x as usize];
+            let subtractions = value / 100 + value / 10 % 10 + value % 10;
+            152 + 16 * subtractions as u32
+        }
+        Instruction::Store { x } | Instruction::Load { x } => 100 + 14 * x as u32,
+        _ => 74,
+    }
+}
@@ -0,0 +1,257 @@
+use std::fmt::{self, Write as _};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use super::chip8::Chip8;
+use super::commons::parse_number;
+use super::disasm::Syntax;
+use super::instruction::Instruction;
+use super::timing::{self, Timing};
+
+/// Layout of the trace lines.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Cycle count, address, opcode and classic disassembly of the instruction, followed by the
+    /// registers after its execution:
+    ///
+    /// ```text
+    ///          0 0200 6000     LD V0, 0x00          V0=00 V1=00 ... VF=00 I=0000
+    /// ```
+    #[default]
+    Rchip8,
+    /// State line of the Cadmium emulator (chiplet), with the registers, the stack depth, the
+    /// address and the opcode before the execution of the instruction:
+    ///
+    /// ```text
+    /// V0:00 V1:00 V2:00 ... VF:00 I:0000 SP:0 PC:0200 O:6000
+    /// ```
+    Cadmium,
+}
+
+/// Name of the format, as accepted by `from_str`.
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Format::Rchip8 => "rchip8",
+            Format::Cadmium => "cadmium",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rchip8" => Ok(Format::Rchip8),
+            "cadmium" => Ok(Format::Cadmium),
+            _ => Err(format!(
+                "unknown trace format `{}`, expected `rchip8` or `cadmium`",
+                s
+            )),
+        }
+    }
+}
+
+/// An instruction waiting for its results to be logged.
+struct Pending {
+    /// Cycles elapsed before the instruction.
+    cycles: u64,
+    pc: u16,
+    bytes: String,
+    text: String,
+}
+
+/// The last recorded instruction and the registers it was executed with, to count its cycles.
+struct Executed {
+    instruction: Option<Instruction>,
+    v: [u8; 16],
+    i: u16,
+    pc: u16,
+}
+
+/// Execution trace, one line per executed instruction (see `Format`).
+///
+/// Cycles are counted for every executed instruction, even the ones that are not logged: the
+/// machine cycles of their COSMAC VIP cost with `Timing::Vip` (see `timing::vip_cycles`), or
+/// one per instruction otherwise.
+///
+/// The frontend calls `record` before each instruction (see `Chip8::run_frame_until`), which
+/// logs the previous one, and `finish` at the end of the run.
+pub struct Trace {
+    out: Box<dyn Write>,
+    format: Format,
+    range: Option<RangeInclusive<u16>>,
+    limit: Option<u64>,
+    /// Lines logged so far.
+    lines: u64,
+    cycles: u64,
+    executed: Option<Executed>,
+    pending: Option<Pending>,
+    /// First write error, reported by `finish`.
+    error: Option<io::Error>,
+}
+
+impl Trace {
+    /// Create a trace written to `out`.
+    pub fn new(out: Box<dyn Write>) -> Trace {
+        Trace {
+            out,
+            format: Format::default(),
+            range: None,
+            limit: None,
+            lines: 0,
+            cycles: 0,
+            executed: None,
+            pending: None,
+            error: None,
+        }
+    }
+
+    /// Create a trace written to a file.
+    pub fn create(path: &str) -> Result<Trace, String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(Trace::new(Box::new(BufWriter::new(file))))
+    }
+
+    /// Set the layout of the lines.
+    pub fn set_format(&mut self, format: Format) {
+        self.format = format;
+    }
+
+    /// Only log the instructions at addresses in a range.
+    pub fn set_range(&mut self, range: RangeInclusive<u16>) {
+        self.range = Some(range);
+    }
+
+    /// Stop logging after a number of lines.
+    pub fn set_limit(&mut self, limit: u64) {
+        self.limit = Some(limit);
+    }
+
+    /// Record the instruction about to be executed, and log the previous one.
+    pub fn record(&mut self, chip8: &Chip8) {
+        self.count_cycles(chip8);
+        self.log_pending(chip8);
+        let instruction = chip8.instruction_at(chip8.pc);
+        self.executed = Some(Executed {
+            instruction,
+            v: chip8.v,
+            i: chip8.i,
+            pc: chip8.pc,
+        });
+        if self.limit.is_some_and(|limit| self.lines >= limit)
+            || self
+                .range
+                .as_ref()
+                .is_some_and(|range| !range.contains(&chip8.pc))
+        {
+            return;
+        }
+        self.lines += 1;
+        if self.format == Format::Cadmium {
+            let mut line = String::new();
+            for (k, v) in chip8.v.iter().enumerate() {
+                write!(line, "V{:X}:{:02x} ", k, v).unwrap();
+            }
+            let opcode = chip8
+                .memory
+                .get(chip8.pc as usize..chip8.pc as usize + 2)
+                .map_or(0, |bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+            writeln!(
+                line,
+                "I:{:04x} SP:{:x} PC:{:04x} O:{:04x}",
+                chip8.i,
+                chip8.stack.len(),
+                chip8.pc,
+                opcode
+            )
+            .unwrap();
+            self.write(&line);
+            return;
+        }
+        let (size, text) = match instruction {
+            Some(instruction) => (instruction.size(), Syntax::Classic.format(&instruction)),
+            None => (2, "???".to_string()),
+        };
+        let start = chip8.pc as usize;
+        let bytes = chip8
+            .memory
+            .get(start..start + size)
+            .unwrap_or_default()
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        self.pending = Some(Pending {
+            cycles: self.cycles,
+            pc: chip8.pc,
+            bytes,
+            text,
+        });
+    }
+
+    /// Log the last instruction and flush the output.
+    pub fn finish(&mut self, chip8: &Chip8) -> Result<(), String> {
+        self.log_pending(chip8);
+        if let Err(e) = self.out.flush() {
+            self.error.get_or_insert(e);
+        }
+        match self.error.take() {
+            Some(e) => Err(format!("unable to write the trace: {}", e)),
+            None => Ok(()),
+        }
+    }
+
+    /// Add the cycles of the last recorded instruction, now that it was executed.
+    fn count_cycles(&mut self, chip8: &Chip8) {
+        let Some(executed) = self.executed.take() else {
+            return;
+        };
+        self.cycles += match (chip8.timing, executed.instruction) {
+            (Timing::Vip, Some(instruction)) => {
+                let next = executed.pc.wrapping_add(instruction.size() as u16);
+                let skipped = chip8.pc != next;
+                timing::vip_cycles(&instruction, &executed.v, executed.i, skipped) as u64
+            }
+            _ => 1,
+        };
+    }
+
+    fn log_pending(&mut self, chip8: &Chip8) {
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+        let mut line = format!(
+            "{:>10} {:04X} {:<8} {:<20}",
+            pending.cycles, pending.pc, pending.bytes, pending.text
+        );
+        for (k, v) in chip8.v.iter().enumerate() {
+            write!(line, " V{:X}={:02X}", k, v).unwrap();
+        }
+        writeln!(line, " I={:04X}", chip8.i).unwrap();
+        self.write(&line);
+    }
+
+    fn write(&mut self, line: &str) {
+        if self.error.is_none() {
+            if let Err(e) = self.out.write_all(line.as_bytes()) {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+/// Parse a range of addresses written as `START-END` (inclusive), e.g. `0x200-0x2FF`.
+pub fn parse_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+    let (start, end) = text
+        .split_once('-')
+        .ok_or_else(|| format!("invalid range `{}`, expected START-END", text))?;
+    let (start, end) = (parse_number(start)?, parse_number(end)?);
+    if start > end {
+        return Err(format!("invalid range `{}`, START is after END", text));
+    }
+    Ok(start..=end)
+}
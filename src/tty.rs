@@ -0,0 +1,296 @@
+use std::io::{self, Stdout, Write};
+use std::time::Duration;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use super::chip8::KeyInput;
+use super::commons::CanTick;
+use super::framebuffer::Framebuffer;
+
+/// A terminal in raw mode and full screen, restored when dropped.
+pub struct Screen {
+    out: Stdout,
+    active: bool,
+    /// The terminal reports key releases (kitty keyboard protocol).
+    releases: bool,
+}
+
+impl Screen {
+    /// Switch the terminal to raw mode and full screen, asking for key releases if supported.
+    pub fn new() -> Result<Screen, String> {
+        let mut out = io::stdout();
+        terminal::enable_raw_mode().map_err(|e| e.to_string())?;
+        execute!(out, EnterAlternateScreen, Hide, Clear(ClearType::All))
+            .map_err(|e| e.to_string())?;
+        let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if releases {
+            execute!(
+                out,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        Ok(Screen {
+            out,
+            active: true,
+            releases,
+        })
+    }
+
+    /// Check if the terminal reports key releases.
+    pub fn reports_releases(&self) -> bool {
+        self.releases
+    }
+
+    /// Give the terminal back to the shell, before exiting.
+    pub fn restore(&mut self) {
+        if self.active {
+            self.active = false;
+            if self.releases {
+                execute!(self.out, PopKeyboardEnhancementFlags).unwrap_or(());
+            }
+            execute!(self.out, Show, LeaveAlternateScreen).unwrap_or(());
+            terminal::disable_raw_mode().unwrap_or(());
+        }
+    }
+
+    /// Write lines from the top left corner, padded to the width of the terminal.
+    pub fn draw(&mut self, lines: &[String]) -> Result<(), String> {
+        let (columns, rows) = terminal::size().map_err(|e| e.to_string())?;
+        for (y, line) in lines.iter().take(rows as usize).enumerate() {
+            // leave the last cell alone, some terminals scroll when it is written
+            let width = if y + 1 == rows as usize {
+                columns as usize - 1
+            } else {
+                columns as usize
+            };
+            let line: String = line
+                .chars()
+                .chain(std::iter::repeat(' '))
+                .take(width)
+                .collect();
+            queue!(self.out, MoveTo(0, y as u16), Print(line)).map_err(|e| e.to_string())?;
+        }
+        self.out.flush().map_err(|e| e.to_string())
+    }
+
+    /// Ring the terminal bell.
+    pub fn bell(&mut self) {
+        execute!(self.out, Print('\x07')).unwrap_or(());
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
+
+/// The keypad, read from the terminal with the same layout as the SDL frontend.
+///
+/// Most terminals only report key presses (repeated while the key is held), so a key stays
+/// down for a timeout after each of them, unless the terminal reports releases.
+pub struct Keypad {
+    /// Frames left before each key is released.
+    frames: [u32; 16],
+    /// Keys pressed since the last tick, which a release keeps down until the next one.
+    pressed: [bool; 16],
+    /// Keys released since the last tick.
+    released: [bool; 16],
+    hold_frames: u32,
+    releases: bool,
+}
+
+impl Keypad {
+    /// Create a keypad releasing keys after a timeout, or on the reported releases.
+    pub fn new(timeout: Duration, releases: bool) -> Keypad {
+        Keypad {
+            frames: [0; 16],
+            pressed: [false; 16],
+            released: [false; 16],
+            hold_frames: ((timeout.as_millis() * 60).div_ceil(1000) as u32).max(1),
+            releases,
+        }
+    }
+
+    /// Handle a key event, returning `false` if it is not a key of the keypad.
+    pub fn handle(&mut self, key: &KeyEvent) -> bool {
+        let KeyCode::Char(c) = key.code else {
+            return false;
+        };
+        let Some(k) = keypad_key(c) else {
+            return false;
+        };
+        let down = self.frames[k] > 0;
+        self.frames[k] = match (key.kind, self.releases) {
+            (KeyEventKind::Release, _) if self.pressed[k] => 1,
+            (KeyEventKind::Release, _) => 0,
+            (_, true) => u32::MAX,
+            (_, false) => self.hold_frames,
+        };
+        match key.kind {
+            KeyEventKind::Release => self.released[k] |= down,
+            _ => self.pressed[k] |= !down,
+        }
+        true
+    }
+
+    /// Get the state of the keys.
+    pub fn keys(&self) -> [bool; 16] {
+        self.frames.map(|frames| frames > 0)
+    }
+
+    /// Get the keys pressed during the last tick.
+    pub fn pressed(&self) -> [bool; 16] {
+        self.pressed
+    }
+
+    /// Get the keys released during the last tick, or whose timeout expired.
+    pub fn released(&self) -> [bool; 16] {
+        self.released
+    }
+
+    /// Get the keys of the next frame.
+    pub fn input(&self) -> KeyInput {
+        KeyInput {
+            down: self.keys(),
+            pressed: self.pressed,
+            released: self.released,
+        }
+    }
+}
+
+impl CanTick for Keypad {
+    /// Release the keys held for long enough, or tapped during the last tick.
+    fn tick(&mut self) {
+        self.pressed = [false; 16];
+        self.released = [false; 16];
+        for (frames, released) in self.frames.iter_mut().zip(self.released.iter_mut()) {
+            if self.releases {
+                // the release of a tapped key was already reported
+                if *frames == 1 {
+                    *frames = 0;
+                }
+            } else if *frames > 0 {
+                *frames -= 1;
+                *released = *frames == 0;
+            }
+        }
+    }
+}
+
+/// Terminal frontend: the screen drawn with half blocks and the keypad read from stdin.
+pub struct Tty {
+    screen: Screen,
+    keypad: Keypad,
+    sound: bool,
+    /// The user pressed Esc or Ctrl-C.
+    pub quit_requested: bool,
+}
+
+impl Tty {
+    /// Take over the terminal.
+    pub fn new(key_timeout: Duration) -> Result<Tty, String> {
+        let screen = Screen::new()?;
+        let keypad = Keypad::new(key_timeout, screen.reports_releases());
+        Ok(Tty {
+            screen,
+            keypad,
+            sound: false,
+            quit_requested: false,
+        })
+    }
+
+    /// Give the terminal back to the shell, before exiting.
+    pub fn restore(&mut self) {
+        self.screen.restore();
+    }
+
+    /// Get the state of the keypad.
+    pub fn keys(&self) -> [bool; 16] {
+        self.keypad.keys()
+    }
+
+    /// Get the keys of the next frame.
+    pub fn input(&self) -> KeyInput {
+        self.keypad.input()
+    }
+
+    /// Draw the screen.
+    pub fn render(&mut self, display: &Framebuffer) -> Result<(), String> {
+        self.screen.draw(&half_blocks(display))
+    }
+
+    /// Ring the bell when the sound starts, terminals cannot play a continuous tone.
+    pub fn set_sound(&mut self, active: bool) {
+        if active && !self.sound {
+            self.screen.bell();
+        }
+        self.sound = active;
+    }
+}
+
+impl CanTick for Tty {
+    /// Read the pending key events.
+    fn tick(&mut self) {
+        self.keypad.tick();
+        loop {
+            match next_event() {
+                Ok(Some(Event::Key(key))) => {
+                    let ctrl_c = key.modifiers.contains(KeyModifiers::CONTROL)
+                        && key.code == KeyCode::Char('c');
+                    if ctrl_c || key.code == KeyCode::Esc {
+                        self.quit_requested = true;
+                    } else {
+                        self.keypad.handle(&key);
+                    }
+                }
+                Ok(Some(_)) => (),
+                Ok(None) => break,
+                Err(_) => {
+                    self.quit_requested = true;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Read the next event, if any, without waiting.
+fn next_event() -> io::Result<Option<Event>> {
+    if event::poll(Duration::ZERO)? {
+        event::read().map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Render the framebuffer as text, with two pixels per character.
+pub fn half_blocks(display: &Framebuffer) -> Vec<String> {
+    (0..display.height() / 2)
+        .map(|y| {
+            (0..display.width())
+                .map(
+                    |x| match (display.is_set(x, 2 * y), display.is_set(x, 2 * y + 1)) {
+                        (false, false) => ' ',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (true, true) => '█',
+                    },
+                )
+                .collect()
+        })
+        .collect()
+}
+
+/// Map a character to a key of the keypad, with the same layout as the SDL frontend.
+fn keypad_key(c: char) -> Option<usize> {
+    "x123qweasdzc4rfv".find(c.to_ascii_lowercase())
+}
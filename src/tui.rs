@@ -0,0 +1,272 @@
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+
+use super::chip8::{Chip8, KeyInput};
+use super::commons::CanTick;
+use super::debugger::{self, Debugger};
+use super::tty::{self, Keypad, Screen};
+
+/// Lines of command output kept in the log pane.
+const LOG_SIZE: usize = 100;
+
+/// Instructions shown before the program counter in the disassembly pane.
+const LIST_BEFORE: u16 = 3;
+
+const HELP: &str =
+    "s step | n next | c continue | esc pause | : command | up/down memory | i follow I | q quit";
+
+/// A grid of characters, drawn at once to avoid flickering.
+struct Canvas {
+    rows: Vec<Vec<char>>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Canvas {
+        Canvas {
+            rows: vec![vec![' '; width]; height],
+        }
+    }
+
+    /// Write a text from a position, clipped to the canvas.
+    fn put(&mut self, x: usize, y: usize, text: &str) {
+        let Some(row) = self.rows.get_mut(y) else {
+            return;
+        };
+        for (cell, c) in row.iter_mut().skip(x).zip(text.chars()) {
+            *cell = c;
+        }
+    }
+
+    /// Write lines from a position, stopping at `height` lines.
+    fn put_lines(&mut self, x: usize, y: usize, height: usize, text: &str) {
+        for (k, line) in text.lines().take(height).enumerate() {
+            self.put(x, y + k, line);
+        }
+    }
+
+    /// Draw a frame with a title around an area.
+    fn put_box(&mut self, x: usize, y: usize, width: usize, height: usize, title: &str) {
+        let top = format!("┌{:─<width$}┐", title, width = width);
+        self.put(x, y, &top);
+        for k in 1..=height {
+            self.put(x, y + k, "│");
+            self.put(x + width + 1, y + k, "│");
+        }
+        self.put(x, y + height + 1, &format!("└{}┘", "─".repeat(width)));
+    }
+}
+
+/// Full-screen terminal debugger, showing the screen, the registers, the stack, the disassembly
+/// around the program counter and a memory dump.
+///
+/// The execution is controlled by a `Debugger`: shortcuts and the commands typed after `:` are
+/// forwarded to it. While the program runs, the keys go to the keypad (see `tty::Keypad`).
+pub struct Tui {
+    screen: Screen,
+    /// Command being typed after `:`.
+    input: Option<String>,
+    /// Output of the commands and reasons of the pauses.
+    log: Vec<String>,
+    /// First address of the memory pane, following `I` if `None`.
+    memory_address: Option<usize>,
+    keypad: Keypad,
+}
+
+impl Tui {
+    /// Take over the terminal; see `Keypad` for the key timeout.
+    pub fn new(key_timeout: Duration) -> Result<Tui, String> {
+        let screen = Screen::new()?;
+        let keypad = Keypad::new(key_timeout, screen.reports_releases());
+        Ok(Tui {
+            screen,
+            input: None,
+            log: Vec::new(),
+            memory_address: None,
+            keypad,
+        })
+    }
+
+    /// Give the terminal back to the shell, before exiting.
+    pub fn restore(&mut self) {
+        self.screen.restore();
+    }
+
+    /// Get the keys of the next frame.
+    pub fn input(&self) -> KeyInput {
+        self.keypad.input()
+    }
+
+    /// Handle the pending key presses.
+    pub fn handle_events(&mut self, chip8: &Chip8, debugger: &mut Debugger) -> Result<(), String> {
+        while event::poll(Duration::ZERO).map_err(|e| e.to_string())? {
+            if let Event::Key(key) = event::read().map_err(|e| e.to_string())? {
+                self.handle_key(chip8, debugger, key);
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, chip8: &Chip8, debugger: &mut Debugger, key: KeyEvent) {
+        // releases only matter to the keypad
+        if key.kind == KeyEventKind::Release {
+            self.keypad.handle(&key);
+            return;
+        }
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.run(chip8, debugger, "quit");
+            return;
+        }
+
+        // command line
+        if let Some(input) = &mut self.input {
+            match key.code {
+                KeyCode::Enter => {
+                    let line = self.input.take().unwrap_or_default();
+                    self.log.push(format!(":{}", line));
+                    self.run(chip8, debugger, &line);
+                }
+                KeyCode::Esc => self.input = None,
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                _ => (),
+            }
+            return;
+        }
+
+        // keypad while running
+        if !debugger.is_paused() {
+            if key.code == KeyCode::Esc {
+                debugger.interrupt();
+            } else {
+                self.keypad.handle(&key);
+            }
+            return;
+        }
+
+        // shortcuts while paused
+        let memory_address = self.memory_address.unwrap_or(chip8.i as usize & !0xF);
+        let last_row = chip8.memory.len() - 16;
+        match key.code {
+            KeyCode::Char('s') | KeyCode::F(11) => self.run(chip8, debugger, "step"),
+            KeyCode::Char('n') | KeyCode::F(10) => self.run(chip8, debugger, "next"),
+            KeyCode::Char('c') | KeyCode::F(5) => self.run(chip8, debugger, "continue"),
+            KeyCode::Char('q') => self.run(chip8, debugger, "quit"),
+            KeyCode::Char(':') => self.input = Some(String::new()),
+            KeyCode::Char('i') => self.memory_address = None,
+            KeyCode::Up => self.memory_address = Some(memory_address.saturating_sub(16)),
+            KeyCode::Down => self.memory_address = Some((memory_address + 16).min(last_row)),
+            KeyCode::PageUp => self.memory_address = Some(memory_address.saturating_sub(256)),
+            KeyCode::PageDown => self.memory_address = Some((memory_address + 256).min(last_row)),
+            _ => (),
+        }
+    }
+
+    /// Execute a debugger command, logging its output.
+    fn run(&mut self, chip8: &Chip8, debugger: &mut Debugger, line: &str) {
+        match debugger.command(chip8, line) {
+            Ok(output) => self.log.extend(output.lines().map(String::from)),
+            Err(err) => self.log.push(err),
+        }
+        let excess = self.log.len().saturating_sub(LOG_SIZE);
+        self.log.drain(..excess);
+    }
+
+    /// Draw all the panes.
+    pub fn draw(
+        &mut self,
+        chip8: &Chip8,
+        debugger: &mut Debugger,
+        frame: usize,
+    ) -> Result<(), String> {
+        if let Some(message) = debugger.take_message() {
+            self.log.extend(message.lines().map(String::from));
+        }
+        let (columns, rows) = terminal::size().map_err(|e| e.to_string())?;
+        let mut canvas = Canvas::new(columns as usize, rows as usize);
+
+        // status
+        let state = if debugger.is_paused() {
+            "paused"
+        } else {
+            "running"
+        };
+        let sound = if chip8.sound_active() { "♪" } else { "" };
+        canvas.put(
+            0,
+            0,
+            &format!("rchip8 | {} | frame {} {}", state, frame, sound),
+        );
+
+        // screen, two pixels per character
+        let (width, height) = (chip8.display.width(), chip8.display.height() / 2);
+        canvas.put_box(0, 1, width, height, "screen");
+        for (y, line) in tty::half_blocks(&chip8.display).iter().enumerate() {
+            canvas.put(1, 2 + y, line);
+        }
+
+        // registers and stack, right of the screen
+        let x = width + 3;
+        canvas.put(x, 1, "registers");
+        canvas.put_lines(x, 2, 3, &debugger::registers(chip8));
+        canvas.put(x, 6, "stack");
+        canvas.put_lines(x, 7, height.saturating_sub(5), &debugger::stack(chip8));
+
+        // disassembly and memory, below the screen
+        let y = height + 4;
+        let pane_height = (rows as usize).saturating_sub(y + 1 + 6);
+        canvas.put(0, y, "disassembly");
+        let start = chip8.pc.saturating_sub(2 * LIST_BEFORE);
+        let listing: String = debugger::list(chip8, start, pane_height)
+            .lines()
+            .map(|line| {
+                let address = line
+                    .get(4..8)
+                    .and_then(|hex| u16::from_str_radix(hex, 16).ok());
+                let marker = match address {
+                    Some(address) if debugger.has_breakpoint_at(address) => '*',
+                    _ => ' ',
+                };
+                format!("{}{}\n", marker, line)
+            })
+            .collect();
+        canvas.put_lines(0, y + 1, pane_height, &listing);
+        let address = self.memory_address.unwrap_or(chip8.i as usize & !0xF);
+        let title = match self.memory_address {
+            Some(_) => "memory",
+            None => "memory (following I)",
+        };
+        canvas.put(42, y, title);
+        canvas.put_lines(
+            42,
+            y + 1,
+            pane_height,
+            &debugger::memory(chip8, address, pane_height * 16),
+        );
+
+        // log and command line
+        let y = (rows as usize).saturating_sub(6);
+        let log = &self.log[self.log.len().saturating_sub(5)..];
+        for (k, line) in log.iter().enumerate() {
+            canvas.put(0, y + k, line);
+        }
+        let prompt = match &self.input {
+            Some(input) => format!(":{}", input),
+            None => HELP.to_string(),
+        };
+        canvas.put(0, y + 5, &prompt);
+
+        let lines: Vec<String> = canvas.rows.iter().map(|row| row.iter().collect()).collect();
+        self.screen.draw(&lines)
+    }
+}
+
+impl CanTick for Tui {
+    /// Release the keys held for long enough.
+    fn tick(&mut self) {
+        self.keypad.tick();
+    }
+}
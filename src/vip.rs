@@ -0,0 +1,202 @@
+use super::cdp1802::{Bus, Cdp1802};
+use super::framebuffer::{Framebuffer, HEIGHT};
+use super::memory::PROGRAM_START;
+
+/// Size of the RAM, at 0x0000 (a VIP expanded to 4KB, as expected by CHIP-8 programs).
+pub const RAM_SIZE: usize = 0x1000;
+
+/// Size of the monitor ROM, at 0x8000 and mirrored up to 0xFFFF.
+pub const ROM_SIZE: usize = 0x200;
+
+/// Address of the monitor ROM.
+const ROM_START: u16 = 0x8000;
+
+/// Machine cycles in a line of the 1861 video chip.
+const LINE_CYCLES: u32 = 14;
+
+/// Lines in a frame of the 1861, at 60Hz.
+const FRAME_LINES: u32 = 262;
+
+/// First displayed line.
+const DISPLAY_START: u32 = 80;
+
+/// Displayed lines, each of them fetching 8 bytes by DMA.
+const DISPLAY_LINES: u32 = 128;
+
+/// The 1861 requests an interrupt two lines before the display starts.
+const INTERRUPT_LINE: u32 = DISPLAY_START - 2;
+
+/// `EF1` is asserted during the 4 lines before the display, and the last 4 displayed lines.
+const EF1_LINES: u32 = 4;
+
+/// Devices of the VIP seen by the CPU: memory, keypad, video chip.
+struct Hardware {
+    ram: Vec<u8>,
+    rom: Vec<u8>,
+    /// After a reset the ROM is also read at 0x0000, until an address above 0x8000 is read.
+    boot: bool,
+    keys: [bool; 16],
+    /// Key selected with `OUT 2`, whose state is reported on `EF3`.
+    key_latch: u8,
+    /// 1861 turned on with `INP 1` (and off with `OUT 1`).
+    display_on: bool,
+    ef1: bool,
+}
+
+impl Bus for Hardware {
+    fn read(&mut self, address: u16) -> u8 {
+        if address >= ROM_START {
+            self.boot = false;
+        }
+        if address >= ROM_START || self.boot {
+            self.rom[address as usize % ROM_SIZE]
+        } else {
+            *self.ram.get(address as usize).unwrap_or(&0)
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if let Some(byte) = self.ram.get_mut(address as usize) {
+            *byte = value;
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            1 => self.display_on = false,
+            2 => self.key_latch = value & 0xF,
+            _ => (),
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            self.display_on = true;
+        }
+        0
+    }
+
+    fn flag(&self, n: u8) -> bool {
+        match n {
+            1 => self.ef1,
+            3 => self.keys[self.key_latch as usize],
+            _ => false,
+        }
+    }
+}
+
+/// RCA COSMAC VIP, running the original CHIP-8 interpreter as CDP1802 machine code.
+///
+/// The machine starts in the monitor ROM, which hands over to the interpreter at 0x0000 since
+/// the `C` key is not held, and the interpreter then runs the program at 0x200. The 1861 video
+/// chip interrupts the CPU every frame and reads the display by DMA, one line of 8 bytes every
+/// 14 machine cycles; the CHIP-8 screen is made of the first of each group of 4 lines, which the
+/// interrupt routine repeats. The sound is on while `Q` is set.
+///
+/// Neither the monitor nor the interpreter can be distributed with the emulator, their images
+/// have to be supplied by the user.
+pub struct Vip {
+    cpu: Cdp1802,
+    hardware: Hardware,
+    /// Screen, as read by the 1861 during the last frame.
+    pub display: Framebuffer,
+    /// Machine cycles of the last instruction of a line spent in the next one.
+    overrun: u32,
+}
+
+impl Vip {
+    /// Create a VIP with the monitor ROM, the CHIP-8 interpreter loaded at 0x0000 and a program
+    /// at 0x200.
+    pub fn new(monitor: &[u8], interpreter: &[u8], program: &[u8]) -> Result<Vip, String> {
+        if monitor.len() > ROM_SIZE {
+            return Err(format!(
+                "monitor ROM is too big ({} bytes, the maximum is {})",
+                monitor.len(),
+                ROM_SIZE
+            ));
+        }
+        if interpreter.len() > PROGRAM_START {
+            return Err(format!(
+                "interpreter is too big ({} bytes, the maximum is {})",
+                interpreter.len(),
+                PROGRAM_START
+            ));
+        }
+        if program.len() > RAM_SIZE - PROGRAM_START {
+            return Err(format!(
+                "program is too big ({} bytes, the maximum is {})",
+                program.len(),
+                RAM_SIZE - PROGRAM_START
+            ));
+        }
+        let mut rom = monitor.to_vec();
+        rom.resize(ROM_SIZE, 0);
+        let mut ram = vec![0; RAM_SIZE];
+        ram[..interpreter.len()].copy_from_slice(interpreter);
+        ram[PROGRAM_START..PROGRAM_START + program.len()].copy_from_slice(program);
+        Ok(Vip {
+            cpu: Cdp1802::new(),
+            hardware: Hardware {
+                ram,
+                rom,
+                boot: true,
+                keys: [false; 16],
+                key_latch: 0,
+                display_on: false,
+                ef1: false,
+            },
+            display: Framebuffer::new(),
+            overrun: 0,
+        })
+    }
+
+    /// Set the state of all the 16 keys of the hexadecimal keypad.
+    pub fn set_keys(&mut self, keys: [bool; 16]) {
+        self.hardware.keys = keys;
+    }
+
+    /// Check if a beep should be playing.
+    pub fn sound_active(&self) -> bool {
+        self.cpu.q
+    }
+
+    /// Run one 60Hz frame of the 1861, then update the screen.
+    ///
+    /// Each line lasts 14 machine cycles: on a displayed line the 8 DMA cycles come first, then
+    /// the CPU runs until the line is over, an instruction crossing the end of the line taking
+    /// its remaining cycles from the next one. The interrupt is requested during the 2 lines
+    /// before the display, and `EF1` is asserted during the 4 lines before and the last 4
+    /// displayed lines. All 128 lines are read, but only the first of each group of 4 is kept
+    /// for the 32 rows of the screen: the interpreter repeats each row 4 times.
+    pub fn run_frame(&mut self) {
+        let display_lines = DISPLAY_START..DISPLAY_START + DISPLAY_LINES;
+        let mut rows = [[0u8; 8]; HEIGHT];
+        for line in 0..FRAME_LINES {
+            let display_on = self.hardware.display_on;
+            self.hardware.ef1 = display_on
+                && (line + EF1_LINES >= display_lines.start && line < display_lines.start
+                    || line + EF1_LINES >= display_lines.end && line < display_lines.end);
+            let mut cycles = self.overrun;
+            if display_on && display_lines.contains(&line) {
+                let bytes = std::array::from_fn(|_| self.cpu.dma_out(&mut self.hardware));
+                let line = line - DISPLAY_START;
+                if line.is_multiple_of(4) {
+                    rows[line as usize / 4] = bytes;
+                }
+                cycles += 8;
+            }
+            while cycles < LINE_CYCLES {
+                let interrupt = display_on && (INTERRUPT_LINE..DISPLAY_START).contains(&line);
+                cycles += if interrupt && self.cpu.interrupt() {
+                    1
+                } else {
+                    self.cpu.step(&mut self.hardware)
+                };
+            }
+            self.overrun = cycles - LINE_CYCLES;
+        }
+        for (y, row) in rows.iter().enumerate() {
+            self.display.set_row(y, row);
+        }
+    }
+}
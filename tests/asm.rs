@@ -0,0 +1,35 @@
+//! Assembler tests: the bytes of a few statements, and the errors of invalid sources.
+
+use rchip8::asm::assemble;
+
+#[test]
+fn statements() {
+    let rom =
+        assemble(": main\n  v1 := 0x20\n  i := sprite\n  jump main\n: sprite\n  0x3C").unwrap();
+    assert_eq!(rom, [0x61, 0x20, 0xA2, 0x06, 0x12, 0x00, 0x3C]);
+}
+
+#[test]
+fn errors() {
+    let cases = [
+        ("jump nowhere", "<source>:1: undefined label `nowhere`"),
+        (": a\n: a", "<source>:2: label `a` defined twice"),
+        ("v0 := 256", "<source>:1: value 256 does not fit in a byte"),
+        ("v0 := vZ", "<source>:1: expected a number, found `vZ`"),
+        ("v0 ?= 1", "<source>:1: unknown operator `?=`"),
+        (
+            "loop\n  while v0 == 1",
+            "<source>:1: `loop` is never closed",
+        ),
+        ("end", "<source>:1: `end` without `if ... begin`"),
+        ("plane 4", "<source>:1: plane must be between 0 and 3"),
+        ("v0 :=", "<source>:1: unexpected end of file after `v0`"),
+        (
+            ":org 0x100",
+            "<source>:1: origin must be between 0x200 and 0xFFFF",
+        ),
+    ];
+    for (source, error) in cases {
+        assert_eq!(assemble(source).unwrap_err(), error, "{:?}", source);
+    }
+}
@@ -0,0 +1,244 @@
+//! CDP1802 instruction tests: hand-assembled snippets run on a bare 64KB memory.
+
+use rchip8::cdp1802::{Bus, Cdp1802};
+
+/// 64KB of RAM, the bytes sent with `OUT` and the external flags.
+struct Memory {
+    ram: Vec<u8>,
+    outputs: Vec<(u8, u8)>,
+    flags: [bool; 4],
+}
+
+impl Bus for Memory {
+    fn read(&mut self, address: u16) -> u8 {
+        self.ram[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.ram[address as usize] = value;
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        self.outputs.push((port, value));
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        0x10 | port
+    }
+
+    fn flag(&self, n: u8) -> bool {
+        self.flags[n as usize - 1]
+    }
+}
+
+/// A CPU about to run `code` from address 0, with `R2` pointing to a stack at 0x1000.
+fn setup(code: &[u8]) -> (Cdp1802, Memory) {
+    let mut memory = Memory {
+        ram: vec![0; 0x10000],
+        outputs: Vec::new(),
+        flags: [false; 4],
+    };
+    memory.ram[..code.len()].copy_from_slice(code);
+    let mut cpu = Cdp1802::new();
+    cpu.r[2] = 0x1000;
+    (cpu, memory)
+}
+
+/// Run `steps` instructions, returning the machine cycles they took.
+fn run(cpu: &mut Cdp1802, memory: &mut Memory, steps: usize) -> u32 {
+    (0..steps).map(|_| cpu.step(memory)).sum()
+}
+
+/// Run `SEX 2` then an operation on `M(R2)`, with `D`, `DF` and the memory operand given.
+fn arithmetic(opcode: u8, d: u8, df: bool, operand: u8) -> (u8, bool) {
+    let (mut cpu, mut memory) = setup(&[0xE2, opcode]);
+    memory.ram[0x1000] = operand;
+    cpu.d = d;
+    cpu.df = df;
+    run(&mut cpu, &mut memory, 2);
+    (cpu.d, cpu.df)
+}
+
+#[test]
+fn subtract() {
+    // SD: M - D, DF set when there is no borrow
+    assert_eq!(arithmetic(0xF5, 3, false, 5), (2, true));
+    assert_eq!(arithmetic(0xF5, 5, false, 3), (0xFE, false));
+    assert_eq!(arithmetic(0xF5, 5, false, 5), (0, true));
+    // SM: D - M
+    assert_eq!(arithmetic(0xF7, 5, false, 3), (2, true));
+    assert_eq!(arithmetic(0xF7, 3, true, 5), (0xFE, false));
+    // SDB and SMB subtract one more when DF is clear
+    assert_eq!(arithmetic(0x75, 3, true, 5), (2, true));
+    assert_eq!(arithmetic(0x75, 3, false, 5), (1, true));
+    assert_eq!(arithmetic(0x75, 5, false, 5), (0xFF, false));
+    assert_eq!(arithmetic(0x77, 5, true, 3), (2, true));
+    assert_eq!(arithmetic(0x77, 5, false, 3), (1, true));
+    assert_eq!(arithmetic(0x77, 3, false, 3), (0xFF, false));
+}
+
+#[test]
+fn add() {
+    assert_eq!(arithmetic(0xF4, 0x80, true, 0x7F), (0xFF, false));
+    assert_eq!(arithmetic(0xF4, 0x80, false, 0x80), (0, true));
+    // ADC adds DF
+    assert_eq!(arithmetic(0x74, 0x80, true, 0x7F), (0, true));
+}
+
+#[test]
+fn shifts() {
+    // SHR and SHL shift a 0 in, without reading an operand
+    let (mut cpu, mut memory) = setup(&[0xF6, 0xFE]);
+    cpu.d = 0x81;
+    cpu.df = true;
+    run(&mut cpu, &mut memory, 1);
+    assert_eq!((cpu.d, cpu.df, cpu.r[0]), (0x40, true, 1));
+    run(&mut cpu, &mut memory, 1);
+    assert_eq!((cpu.d, cpu.df, cpu.r[0]), (0x80, false, 2));
+    // SHRC and SHLC shift DF in
+    let (mut cpu, mut memory) = setup(&[0x76, 0x7E, 0x7E]);
+    cpu.d = 0x02;
+    cpu.df = true;
+    run(&mut cpu, &mut memory, 1);
+    assert_eq!((cpu.d, cpu.df), (0x81, false));
+    run(&mut cpu, &mut memory, 1);
+    assert_eq!((cpu.d, cpu.df), (0x02, true));
+    run(&mut cpu, &mut memory, 1);
+    assert_eq!((cpu.d, cpu.df), (0x05, false));
+}
+
+#[test]
+fn short_branch() {
+    // BR at the end of a page jumps in the page of its immediate byte
+    let (mut cpu, mut memory) = setup(&[]);
+    memory.ram[0x1FF..0x201].copy_from_slice(&[0x30, 0x10]);
+    cpu.r[0] = 0x1FF;
+    assert_eq!(run(&mut cpu, &mut memory, 1), 2);
+    assert_eq!(cpu.r[0], 0x210);
+    // not taken, the immediate byte is skipped
+    let (mut cpu, mut memory) = setup(&[]);
+    memory.ram[0x1FF..0x201].copy_from_slice(&[0x32, 0x10]);
+    cpu.r[0] = 0x1FF;
+    cpu.d = 1;
+    run(&mut cpu, &mut memory, 1);
+    assert_eq!(cpu.r[0], 0x201);
+    // B1 and BN1 test EF1
+    let (mut cpu, mut memory) = setup(&[0x34, 0x40, 0x3C, 0x50]);
+    run(&mut cpu, &mut memory, 1);
+    assert_eq!(cpu.r[0], 2);
+    run(&mut cpu, &mut memory, 1);
+    assert_eq!(cpu.r[0], 0x50);
+    let (mut cpu, mut memory) = setup(&[0x34, 0x40]);
+    memory.flags[0] = true;
+    run(&mut cpu, &mut memory, 1);
+    assert_eq!(cpu.r[0], 0x40);
+}
+
+#[test]
+fn long_branch() {
+    let (mut cpu, mut memory) = setup(&[0xC0, 0x12, 0x34]);
+    assert_eq!(run(&mut cpu, &mut memory, 1), 3);
+    assert_eq!(cpu.r[0], 0x1234);
+    // LBNZ not taken
+    let (mut cpu, mut memory) = setup(&[0xCA, 0x12, 0x34]);
+    run(&mut cpu, &mut memory, 1);
+    assert_eq!(cpu.r[0], 3);
+    cpu.r[0] = 0;
+    cpu.d = 1;
+    run(&mut cpu, &mut memory, 1);
+    assert_eq!(cpu.r[0], 0x1234);
+
+    // skips and NOP: opcode, Q, D, DF, IE, expected R0 after the instruction
+    let cases = [
+        (0xC4, false, 0, false, false, 1),
+        (0xC8, false, 0, false, false, 3),
+        (0xC5, false, 0, false, false, 3),
+        (0xC5, true, 0, false, false, 1),
+        (0xCD, true, 0, false, false, 3),
+        (0xCD, false, 0, false, false, 1),
+        (0xC6, false, 1, false, false, 3),
+        (0xC6, false, 0, false, false, 1),
+        (0xCE, false, 0, false, false, 3),
+        (0xCE, false, 1, false, false, 1),
+        (0xC7, false, 0, false, false, 3),
+        (0xC7, false, 0, true, false, 1),
+        (0xCF, false, 0, true, false, 3),
+        (0xCF, false, 0, false, false, 1),
+        (0xCC, false, 0, false, true, 3),
+        (0xCC, false, 0, false, false, 1),
+    ];
+    for (opcode, q, d, df, ie, pc) in cases {
+        let (mut cpu, mut memory) = setup(&[opcode, 0x12, 0x34]);
+        (cpu.q, cpu.d, cpu.df, cpu.ie) = (q, d, df, ie);
+        assert_eq!(run(&mut cpu, &mut memory, 1), 3, "{:02X}", opcode);
+        assert_eq!(
+            cpu.r[0], pc,
+            "{:02X} q={} d={} df={} ie={}",
+            opcode, q, d, df, ie
+        );
+    }
+}
+
+#[test]
+fn return_and_disable() {
+    // RET: X and P from M(R(X)), R(X) incremented, interrupts enabled
+    let (mut cpu, mut memory) = setup(&[0xE2, 0x70]);
+    memory.ram[0x1000] = 0x35;
+    cpu.ie = false;
+    run(&mut cpu, &mut memory, 2);
+    assert_eq!((cpu.x, cpu.p, cpu.r[2], cpu.ie), (3, 5, 0x1001, true));
+    // DIS: the same, interrupts disabled
+    let (mut cpu, mut memory) = setup(&[0xE2, 0x71]);
+    memory.ram[0x1000] = 0x35;
+    run(&mut cpu, &mut memory, 2);
+    assert_eq!((cpu.x, cpu.p, cpu.r[2], cpu.ie), (3, 5, 0x1001, false));
+}
+
+#[test]
+fn save_and_mark() {
+    // SAV: T to M(R(X))
+    let (mut cpu, mut memory) = setup(&[0xE2, 0x78]);
+    cpu.t = 0x4A;
+    run(&mut cpu, &mut memory, 2);
+    assert_eq!(memory.ram[0x1000], 0x4A);
+    // MARK: XP to T and M(R2), X = P, R2 decremented
+    let (mut cpu, mut memory) = setup(&[0xE7, 0x79]);
+    run(&mut cpu, &mut memory, 2);
+    assert_eq!((cpu.t, memory.ram[0x1000]), (0x70, 0x70));
+    assert_eq!((cpu.x, cpu.p, cpu.r[2]), (0, 0, 0x0FFF));
+}
+
+#[test]
+fn interrupt() {
+    // the interrupt saves XP in T and runs R1 with R2 as data pointer, then SAV and RET
+    // return to the interrupted program
+    let (mut cpu, mut memory) = setup(&[0xE3, 0x7B, 0x30, 0x02]);
+    memory.ram[0x100..0x103].copy_from_slice(&[0x22, 0x78, 0x70]);
+    cpu.r[1] = 0x100;
+    run(&mut cpu, &mut memory, 2);
+    assert!(cpu.interrupt());
+    assert!(!cpu.interrupt());
+    assert_eq!((cpu.t, cpu.x, cpu.p, cpu.ie), (0x30, 2, 1, false));
+    run(&mut cpu, &mut memory, 3);
+    assert_eq!(
+        (cpu.x, cpu.p, cpu.ie, cpu.r[0], cpu.r[2]),
+        (3, 0, true, 2, 0x1000)
+    );
+    assert!(cpu.q);
+}
+
+#[test]
+fn input_output() {
+    // OUT 4 sends M(R(X)) and increments R(X), INP 4 stores the input in D and M(R(X))
+    let (mut cpu, mut memory) = setup(&[0xE2, 0x64, 0x6C, 0x00]);
+    memory.ram[0x1000] = 0xAB;
+    run(&mut cpu, &mut memory, 3);
+    assert_eq!(memory.outputs, vec![(4, 0xAB)]);
+    assert_eq!((cpu.d, memory.ram[0x1001], cpu.r[2]), (0x14, 0x14, 0x1001));
+    // IDL waits for an interrupt or DMA
+    run(&mut cpu, &mut memory, 1);
+    assert!(cpu.is_idle());
+    assert_eq!(run(&mut cpu, &mut memory, 1), 1);
+    assert_eq!(cpu.dma_out(&mut memory), 0x00);
+    assert!(!cpu.is_idle());
+}
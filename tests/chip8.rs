@@ -0,0 +1,63 @@
+//! Machine tests that are easier to set up directly than with a test ROM.
+
+use rchip8::chip8::Chip8;
+use rchip8::platform::Platform;
+
+#[test]
+fn program_counter_wraps() {
+    // XO-CHIP programs can run up to the end of the 64KB memory
+    let mut chip8 = Chip8::with_platform(Platform::XoChip, 500);
+    chip8.memory[0xFFFC..].copy_from_slice(&[0x30, 0x00, 0x60, 0x01]);
+    chip8.pc = 0xFFFE;
+    chip8.step().unwrap();
+    assert_eq!((chip8.pc, chip8.v[0]), (0x0000, 1));
+    // a skip wraps too
+    chip8.v[0] = 0;
+    chip8.pc = 0xFFFC;
+    chip8.step().unwrap();
+    assert_eq!(chip8.pc, 0x0000);
+}
+
+/// Run a program from 0x200 one instruction at a time, with 32 0xFF bytes at 0x300.
+fn run(chip8: &mut Chip8, program: &[u8]) {
+    chip8.memory[0x200..0x200 + program.len()].copy_from_slice(program);
+    chip8.memory[0x300..0x320].fill(0xFF);
+    for _ in 0..program.len() / 2 {
+        chip8.step().unwrap();
+    }
+}
+
+#[test]
+fn collision_rows() {
+    // high resolution: VF counts the rows that collided, then the rows clipped at the bottom
+    let mut chip8 = Chip8::with_platform(Platform::SuperChip, 500);
+    let program = [
+        0x00, 0xFF, 0xA3, 0x00, 0x60, 0x00, 0x61, 0x00, 0xD0, 0x12, 0xD0, 0x13,
+    ];
+    run(&mut chip8, &program);
+    assert_eq!(chip8.v[0xF], 2);
+    let mut chip8 = Chip8::with_platform(Platform::SuperChip, 500);
+    run(
+        &mut chip8,
+        &[0x00, 0xFF, 0xA3, 0x00, 0x61, 0x7F, 0xD0, 0x14],
+    );
+    assert_eq!(chip8.v[0xF], 3);
+    // with collision=any, VF is 1 on any collision
+    let mut chip8 = Chip8::with_platform(Platform::SuperChip, 500);
+    chip8.quirks.set("collision=any").unwrap();
+    run(&mut chip8, &program);
+    assert_eq!(chip8.v[0xF], 1);
+}
+
+#[test]
+fn lores_tall_sprite() {
+    // low resolution DXY0 draws 8x16 with lores-dxy0=8x16, 16x16 otherwise
+    let program = [0xA3, 0x00, 0x60, 0x00, 0xD0, 0x00];
+    let mut chip8 = Chip8::with_platform(Platform::SuperChip, 500);
+    run(&mut chip8, &program);
+    assert!(chip8.display.is_set(7, 15) && !chip8.display.is_set(8, 0));
+    let mut chip8 = Chip8::with_platform(Platform::SuperChip, 500);
+    chip8.quirks.set("lores-dxy0=16x16").unwrap();
+    run(&mut chip8, &program);
+    assert!(chip8.display.is_set(15, 15));
+}
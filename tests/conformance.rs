@@ -0,0 +1,176 @@
+//! Conformance tests: test ROMs run headlessly for a fixed number of frames, their final screen
+//! compared with a golden image in `tests/golden`.
+//!
+//! The ROMs in `tests/roms` draw a tick for each passing check and a cross for each failing
+//! one, so a golden image can be reviewed by eye. Run with `RCHIP8_BLESS=1` to write the golden
+//! images from the current output instead of comparing them.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use rchip8::asm;
+use rchip8::chip8::Chip8;
+use rchip8::headless::{self, Headless};
+use rchip8::platform::Platform;
+use rchip8::quirks::Quirks;
+use rchip8::timing::Timing;
+
+/// Key script of `keypad.8o`: 5 is pressed then released, and then A; B is held from before the
+/// second wait, during which 7 is pressed and released.
+const KEYPAD_SCRIPT: &str =
+    "10 5 down\n20 5 up\n30 A down\n35 B down\n40 A up\n60 7 down\n70 7 up\n90 B up\n";
+
+/// `KEYPAD_SCRIPT` with 5 and 7 pressed and released within a single frame, while FX0A waits.
+const KEYPAD_TAP_SCRIPT: &str =
+    "10 5 down\n10 5 up\n30 A down\n35 B down\n40 A up\n60 7 down\n60 7 up\n90 B up\n";
+
+/// A test ROM run on a machine configuration.
+struct Case<'a> {
+    /// Name of the golden image.
+    name: &'a str,
+    /// Source in `tests/roms`.
+    rom: &'a str,
+    platform: Platform,
+    /// Quirks preset, defaults to the platform ones.
+    quirks: Option<Quirks>,
+    timing: Timing,
+    frames: u64,
+    keys: &'a str,
+}
+
+impl Case<'_> {
+    fn new<'a>(name: &'a str, rom: &'a str, platform: Platform) -> Case<'a> {
+        Case {
+            name,
+            rom,
+            platform,
+            quirks: None,
+            timing: Timing::Fixed,
+            frames: 120,
+            keys: "",
+        }
+    }
+
+    /// Run the ROM and return the final screen.
+    fn run(&self) -> Result<String, String> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms");
+        let rom = asm::assemble_file(&dir.join(self.rom).to_string_lossy())?;
+        let mut chip8 = Chip8::with_platform(self.platform, 500);
+        if let Some(quirks) = self.quirks {
+            chip8.quirks = quirks;
+        }
+        chip8.timing = self.timing;
+        chip8.load(&rom)?;
+        let mut frontend = Headless::with_events(headless::parse_script(self.keys)?);
+        while frontend.frame() < self.frames {
+            frontend.run_frame(&mut chip8)?;
+        }
+        Ok(chip8.display.to_string())
+    }
+
+    /// Compare the final screen with the golden image, or write it when blessing.
+    fn check(&self) {
+        let screen = self
+            .run()
+            .unwrap_or_else(|e| panic!("{}: {}", self.name, e));
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(format!("{}.txt", self.name));
+        if env::var_os("RCHIP8_BLESS").is_some() {
+            fs::write(&path, &screen).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            return;
+        }
+        let golden = fs::read_to_string(&path).unwrap_or_else(|e| {
+            panic!(
+                "{}: {} (run with RCHIP8_BLESS=1 to create it)",
+                path.display(),
+                e
+            )
+        });
+        if screen != golden {
+            panic!(
+                "{}: screen differs from {}\n\nexpected:\n{}\nactual:\n{}",
+                self.name,
+                path.display(),
+                golden,
+                screen
+            );
+        }
+    }
+}
+
+#[test]
+fn opcodes() {
+    Case::new("opcodes", "opcodes.8o", Platform::Chip8).check();
+}
+
+#[test]
+fn opcodes_vip_timing() {
+    // same results, one check per frame since each draw waits for the end of the frame
+    Case {
+        quirks: Some(Quirks::VIP),
+        timing: Timing::Vip,
+        ..Case::new("opcodes", "opcodes.8o", Platform::Chip8)
+    }
+    .check();
+}
+
+#[test]
+fn flags() {
+    Case::new("flags", "flags.8o", Platform::Chip8).check();
+}
+
+#[test]
+fn quirks() {
+    Case::new("quirks-chip8", "quirks.8o", Platform::Chip8).check();
+    Case {
+        quirks: Some(Quirks::VIP),
+        ..Case::new("quirks-vip", "quirks.8o", Platform::Chip8)
+    }
+    .check();
+    Case {
+        quirks: Some(Quirks::CHIP48),
+        ..Case::new("quirks-chip48", "quirks.8o", Platform::Chip8)
+    }
+    .check();
+    // the VIP timing always waits for the vertical blank, whatever the quirks
+    Case {
+        quirks: Some(Quirks::CHIP48),
+        timing: Timing::Vip,
+        ..Case::new("quirks-chip48-vip-timing", "quirks.8o", Platform::Chip8)
+    }
+    .check();
+    Case::new("quirks-schip", "quirks.8o", Platform::SuperChip).check();
+    Case::new("quirks-xochip", "quirks.8o", Platform::XoChip).check();
+}
+
+#[test]
+fn keypad() {
+    Case {
+        keys: KEYPAD_SCRIPT,
+        ..Case::new("keypad", "keypad.8o", Platform::Chip8)
+    }
+    .check();
+    // FX0A returns the first key down, 5 right away and then B
+    Case {
+        quirks: Some(Quirks {
+            wait_key_down: true,
+            ..Quirks::default()
+        }),
+        keys: KEYPAD_SCRIPT,
+        ..Case::new("keypad-down", "keypad.8o", Platform::Chip8)
+    }
+    .check();
+    // a tap is seen as a press and a release, the same keys are returned
+    Case {
+        keys: KEYPAD_TAP_SCRIPT,
+        ..Case::new("keypad-tap", "keypad.8o", Platform::Chip8)
+    }
+    .check();
+}
+
+#[test]
+fn schip_display() {
+    Case::new("schip", "schip.8o", Platform::SuperChip).check();
+}
@@ -0,0 +1,23 @@
+//! Disassembler tests.
+
+use rchip8::disasm;
+
+#[test]
+fn jump_table() {
+    let rom = [
+        0xB2, 0x04, // 200: jump0 0x204
+        0xAB, 0xCD, // 202: data
+        0x12, 0x0A, // 204: table, jump 0x20A
+        0x12, 0x0E, // 206: jump 0x20E
+        0xFF, 0xFF, // 208: data, ends the table
+        0x00, 0xE0, // 20A: clear
+        0x12, 0x0C, // 20C: jump 0x20C
+        0x00, 0xEE, // 20E: return
+    ];
+    let code: Vec<usize> = disasm::trace_code(&rom)
+        .iter()
+        .enumerate()
+        .filter_map(|(k, &code)| code.then_some(k))
+        .collect();
+    assert_eq!(code, vec![0x0, 0x4, 0x6, 0xA, 0xC, 0xE]);
+}
@@ -0,0 +1,29 @@
+//! Instruction decoding and encoding tests.
+
+use rchip8::instruction::Instruction;
+
+#[test]
+fn encode_round_trip() {
+    // every opcode that decodes encodes back to itself
+    for opcode in 0..=0xFFFF {
+        if let Ok(instruction) = Instruction::decode(opcode) {
+            assert_eq!(instruction.encode(), opcode, "{:?}", instruction);
+            assert_eq!(instruction.to_bytes(), opcode.to_be_bytes());
+        }
+    }
+}
+
+#[test]
+fn long_load() {
+    let instruction = Instruction::decode_long(0xF000, 0x1234).unwrap();
+    assert_eq!(instruction, Instruction::LoadILong { nnnn: 0x1234 });
+    assert_eq!(instruction.size(), 4);
+    assert_eq!(instruction.to_bytes(), [0xF0, 0x00, 0x12, 0x34]);
+}
+
+#[test]
+fn invalid_opcodes() {
+    for opcode in [0x5001, 0x8008, 0x9001, 0xE000, 0xF002 | 0x100, 0xF0FF] {
+        assert_eq!(Instruction::decode(opcode).unwrap_err().opcode, opcode);
+    }
+}
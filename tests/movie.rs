@@ -0,0 +1,42 @@
+//! Movie serialization tests.
+
+use rchip8::chip8::{Chip8, KeyInput};
+use rchip8::movie::{self, Movie};
+use rchip8::platform::Platform;
+use rchip8::timing::Timing;
+
+#[test]
+fn round_trip() {
+    let mut chip8 = Chip8::with_platform(Platform::SuperChip, 1000);
+    chip8.quirks.set("wrap=on").unwrap();
+    chip8.timing = Timing::Vip;
+    chip8.flags[3] = 7;
+    let mut movie = Movie::new(&chip8, 0x1234, 99);
+    movie.push(KeyInput::default());
+    movie.push(KeyInput {
+        down: std::array::from_fn(|k| k % 3 == 0),
+        pressed: std::array::from_fn(|k| k == 3),
+        released: std::array::from_fn(|k| k == 3 || k == 4),
+    });
+    let loaded = Movie::from_bytes(&movie.to_bytes()).unwrap();
+    assert_eq!(loaded, movie);
+    let replay = loaded.machine().unwrap();
+    assert_eq!(replay.quirks, chip8.quirks);
+    assert_eq!((replay.timing, replay.flags), (chip8.timing, chip8.flags));
+}
+
+#[test]
+fn rejected() {
+    let movie = Movie::new(&Chip8::new(500), 0, 0);
+    let mut data = movie.to_bytes();
+    assert_eq!(Movie::from_bytes(&data[1..]).unwrap_err(), "not a movie");
+    data[4..6].copy_from_slice(&(movie::VERSION - 1).to_be_bytes());
+    assert_eq!(
+        Movie::from_bytes(&data).unwrap_err(),
+        format!(
+            "movie format version {} is not supported (expected {})",
+            movie::VERSION - 1,
+            movie::VERSION
+        )
+    );
+}
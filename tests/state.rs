@@ -0,0 +1,73 @@
+//! Save state and rewind tests.
+
+use rchip8::chip8::Chip8;
+use rchip8::rewind::Rewind;
+use rchip8::state;
+
+/// A machine running a loop writing an increasing counter at 0x300.
+fn machine() -> Chip8 {
+    let mut chip8 = Chip8::new(500);
+    // v0 += 1, i := 0x300, save v0, jump 0x200
+    chip8
+        .load(&[0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00])
+        .unwrap();
+    chip8
+}
+
+#[test]
+fn save_and_load() {
+    let mut chip8 = machine();
+    chip8.run_frame().unwrap();
+    let data = state::save(&chip8, 42);
+    let (v, memory) = (chip8.v, chip8.memory.clone());
+    chip8.run_frame().unwrap();
+    state::load(&mut chip8, &data, 42).unwrap();
+    assert_eq!((chip8.v, chip8.memory), (v, memory));
+}
+
+#[test]
+fn load_rejected() {
+    let mut chip8 = machine();
+    let data = state::save(&chip8, 42);
+    chip8.run_frame().unwrap();
+    let v = chip8.v;
+    assert_eq!(
+        state::load(&mut chip8, &data, 43).unwrap_err(),
+        "save state is for a different ROM (hash 000000000000002a, expected 000000000000002b)"
+    );
+    let mut wrong_version = data.clone();
+    wrong_version[4..6].copy_from_slice(&(state::VERSION + 1).to_be_bytes());
+    assert!(state::load(&mut chip8, &wrong_version, 42)
+        .unwrap_err()
+        .starts_with("save state format version"));
+    assert_eq!(
+        state::load(&mut chip8, &data[..data.len() - 1], 42).unwrap_err(),
+        "save state is truncated"
+    );
+    assert_eq!(
+        state::load(&mut chip8, b"garbage", 42).unwrap_err(),
+        "not a save state"
+    );
+    // the machine is left untouched
+    assert_eq!(chip8.v, v);
+}
+
+#[test]
+fn step_back() {
+    let mut chip8 = machine();
+    let mut rewind = Rewind::new(3);
+    let mut counters = Vec::new();
+    for _ in 0..5 {
+        chip8.run_frame().unwrap();
+        rewind.push(&mut chip8);
+        counters.push((chip8.v[0], chip8.memory[0x300], chip8.pc));
+    }
+    // only the last 3 frames are kept, and the last one is the current state
+    for &(v0, byte, pc) in counters[2..4].iter().rev() {
+        assert!(rewind.step_back(&mut chip8));
+        assert_eq!((chip8.v[0], chip8.memory[0x300], chip8.pc), (v0, byte, pc));
+    }
+    let v0 = chip8.v[0];
+    assert!(!rewind.step_back(&mut chip8));
+    assert_eq!(chip8.v[0], v0);
+}
@@ -0,0 +1,52 @@
+//! COSMAC VIP instruction costs, as counted from the interpreter code.
+
+use rchip8::instruction::Instruction;
+use rchip8::timing::vip_cycles;
+
+/// Cost of an opcode, with `VX` and `VY` of a `XY` opcode set to `x` and `y`.
+fn cycles(opcode: u16, x: u8, y: u8) -> u32 {
+    let mut v = [0; 16];
+    v[1] = x;
+    v[2] = y;
+    vip_cycles(&Instruction::decode(opcode).unwrap(), &v, 0x300, false)
+}
+
+#[test]
+fn fixed_costs() {
+    assert_eq!(cycles(0x00E0, 0, 0), 3118);
+    assert_eq!(cycles(0x00EE, 0, 0), 50);
+    assert_eq!(cycles(0x6123, 0, 0), 74);
+    assert_eq!(cycles(0x8120, 0, 0), 80);
+    assert_eq!(cycles(0x8121, 0, 0), 112);
+    assert_eq!(cycles(0x8124, 0, 0), 112);
+}
+
+#[test]
+fn draw() {
+    // 5 rows, aligned and unaligned
+    assert_eq!(cycles(0xD125, 0, 0), 642);
+    assert_eq!(cycles(0xD125, 3, 0), 942);
+    // last column: no second byte
+    assert_eq!(cycles(0xD125, 56, 0), 562);
+    // clipped at the bottom: rows are shifted but not drawn
+    assert_eq!(cycles(0xD125, 0, 31), 438);
+    assert_eq!(cycles(0xD125, 3, 27), 938);
+}
+
+#[test]
+fn digits() {
+    assert_eq!(cycles(0xF133, 0, 0), 152);
+    assert_eq!(cycles(0xF133, 255, 0), 344);
+    assert_eq!(cycles(0xF133, 99, 0), 440);
+}
+
+#[test]
+fn skips_and_carries() {
+    let v = [0; 16];
+    let skip = Instruction::SkipEqImm { x: 0, nn: 0 };
+    assert_eq!(vip_cycles(&skip, &v, 0, false), 78);
+    assert_eq!(vip_cycles(&skip, &v, 0, true), 82);
+    let add = Instruction::AddI { x: 1 };
+    assert_eq!(cycles(0xF11E, 1, 0), 84);
+    assert_eq!(vip_cycles(&add, &[1; 16], 0x2FF, false), 90);
+}
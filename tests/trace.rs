@@ -0,0 +1,82 @@
+//! Execution trace tests, written into a buffer.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use rchip8::chip8::Chip8;
+use rchip8::timing::Timing;
+use rchip8::trace::{Format, Trace};
+
+/// Output shared with the test, to read the trace back.
+#[derive(Clone, Default)]
+struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Program: v0 := 1, v1 := 2, v0 += v1, then jump back to the start.
+const PROGRAM: [u8; 8] = [0x60, 0x01, 0x61, 0x02, 0x80, 0x14, 0x12, 0x00];
+
+/// Trace `steps` instructions of the program, returning the lines.
+fn trace(steps: usize, setup: impl Fn(&mut Trace, &mut Chip8)) -> Vec<String> {
+    let buffer = Buffer::default();
+    let mut trace = Trace::new(Box::new(buffer.clone()));
+    let mut chip8 = Chip8::new(500);
+    chip8.load(&PROGRAM).unwrap();
+    setup(&mut trace, &mut chip8);
+    for _ in 0..steps {
+        trace.record(&chip8);
+        chip8.step().unwrap();
+    }
+    trace.finish(&chip8).unwrap();
+    let text = String::from_utf8(buffer.0.take()).unwrap();
+    text.lines().map(str::to_string).collect()
+}
+
+#[test]
+fn registers_after_execution() {
+    let lines = trace(3, |_, _| ());
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("         0 0200 6001     LD V0, 0x01          V0=01 V1=00"));
+    // the last line is logged by finish
+    assert!(lines[2].starts_with("         2 0204 8014     ADD V0, V1           V0=03 V1=02"));
+    assert!(lines[2].ends_with(" VF=00 I=0000"));
+}
+
+#[test]
+fn range_and_limit() {
+    // the limit counts logged lines, not the instructions outside of the range
+    let lines = trace(20, |trace, _| {
+        trace.set_range(0x204..=0x206);
+        trace.set_limit(3);
+    });
+    let addresses: Vec<&str> = lines.iter().map(|line| &line[11..15]).collect();
+    assert_eq!(addresses, ["0204", "0206", "0204"]);
+    assert!(lines[2].starts_with("         6 0204"));
+}
+
+#[test]
+fn vip_cycles() {
+    let lines = trace(4, |_, chip8| chip8.timing = Timing::Vip);
+    let cycles: Vec<&str> = lines.iter().map(|line| line[..10].trim()).collect();
+    assert_eq!(cycles, ["0", "74", "148", "260"]);
+}
+
+#[test]
+fn cadmium_format() {
+    let lines = trace(3, |trace, _| trace.set_format(Format::Cadmium));
+    assert_eq!(
+        lines[2],
+        "V0:01 V1:02 V2:00 V3:00 V4:00 V5:00 V6:00 V7:00 V8:00 V9:00 VA:00 VB:00 VC:00 VD:00 \
+         VE:00 VF:00 I:0000 SP:0 PC:0204 O:8014"
+    );
+}
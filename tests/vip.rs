@@ -0,0 +1,114 @@
+//! COSMAC VIP tests, with a minimal monitor and interrupt routines assembled by hand instead of
+//! the original images.
+
+use rchip8::vip::Vip;
+
+/// Monitor jumping to the ROM, which ends the boot mapping, then to the interpreter at 0x0000.
+const MONITOR: [u8; 6] = [0xC0, 0x80, 0x03, 0xC0, 0x00, 0x00];
+
+/// Address of the interrupt routine.
+const INTERRUPT: usize = 0x40;
+
+/// Start of the screen read by the routines.
+const PAGE: u16 = 0x300;
+
+/// Setup code at 0x0000: `R1` points to the interrupt routine, `R2` to a stack at 0x01FF, and
+/// the display is turned on with `INP 1` before looping forever, with `R3` as program counter
+/// since DMA uses `R0`.
+const SETUP: [u8; 23] = [
+    0xF8, 0x00, 0xB1, 0xF8, 0x40, 0xA1, // R1 = 0x0040
+    0xF8, 0x01, 0xB2, 0xF8, 0xFF, 0xA2, // R2 = 0x01FF
+    0xF8, 0x00, 0xB3, 0xF8, 0x13, 0xA3, // R3 = 0x0013
+    0xD3, // SEP 3
+    0xE2, 0x69, // 0x13: SEX 2, INP 1
+    0x30, 0x15, // BR 0x15
+];
+
+/// Interrupt routine showing 32 rows of 8 bytes from `PAGE`, like the original interpreter:
+/// `R0` is restored after each DMA line but the last of each group of 4, until `EF1` reports
+/// the end of the display.
+const ROUTINE: [u8; 30] = [
+    0x22, 0x78, // DEC R2, SAV
+    0xF8, 0x03, 0xB0, 0xF8, 0x00, 0xA0, // R0 = 0x0300
+    0x80, // GLO R0
+    0x34, 0x49, // B1 0x49: wait for the first display line
+    0xA0, 0x30, 0x51, // PLO R0, BR 0x51
+    0xA0, 0xE2, 0xE2, // 0x4E: PLO R0, SEX 2, SEX 2
+    0xA0, 0xE2, 0xE2, // 0x51
+    0xA0, 0xE2, 0xE2, // 0x54
+    0x80, 0xE2, 0x3C, 0x4E, // 0x57: GLO R0, SEX 2, BN1 0x4E
+    0x30, 0x3F, // BR 0x3F, to RET
+    0x00,
+];
+
+/// Interrupt routine setting `R0` to 0x0400 and returning once the display started (the
+/// interrupt is requested for two lines), so that each display line reads the next 8 bytes.
+const ROUTINE_NO_REPEAT: [u8; 12] = [
+    0x22, 0x78, // DEC R2, SAV
+    0xF8, 0x04, 0xB0, 0xF8, 0x00, 0xA0, // R0 = 0x0400
+    0x34, 0x48, // B1 0x48
+    0x30, 0x3F, // BR 0x3F, to RET
+];
+
+/// Byte at an address of the program.
+fn pattern(address: u16) -> u8 {
+    (address as u8).wrapping_mul(37) ^ (address >> 8) as u8 ^ 0xA5
+}
+
+fn interpreter(routine: &[u8]) -> Vec<u8> {
+    let mut interpreter = vec![0; 0x200];
+    interpreter[..SETUP.len()].copy_from_slice(&SETUP);
+    // RET, just before the routine
+    interpreter[INTERRUPT - 1] = 0x70;
+    interpreter[INTERRUPT..INTERRUPT + routine.len()].copy_from_slice(routine);
+    interpreter
+}
+
+fn program() -> Vec<u8> {
+    (0x200..0x800).map(pattern).collect()
+}
+
+/// Check that each row of the screen shows 8 bytes, starting at `start(row)`.
+fn check_screen(vip: &Vip, start: impl Fn(u16) -> u16) {
+    for y in 0..32 {
+        for x in 0..64 {
+            let byte = pattern(start(y as u16) + x as u16 / 8);
+            let bit = (byte >> (7 - x % 8)) & 1 != 0;
+            assert_eq!(vip.display.is_set(x, y), bit, "pixel {},{}", x, y);
+        }
+    }
+}
+
+#[test]
+fn display_dma() {
+    let mut vip = Vip::new(&MONITOR, &interpreter(&ROUTINE), &program()).unwrap();
+    for _ in 0..3 {
+        vip.run_frame();
+        check_screen(&vip, |row| PAGE + 8 * row);
+    }
+}
+
+#[test]
+fn display_lines() {
+    // all 128 lines read memory, a row showing the first line of its group
+    let mut vip = Vip::new(&MONITOR, &interpreter(&ROUTINE_NO_REPEAT), &program()).unwrap();
+    vip.run_frame();
+    check_screen(&vip, |row| 0x400 + 32 * row);
+}
+
+#[test]
+fn display_off() {
+    // without INP 1 the 1861 neither interrupts nor reads the memory
+    let mut interpreter = interpreter(&ROUTINE);
+    interpreter[0x14] = 0xC4;
+    let mut vip = Vip::new(&MONITOR, &interpreter, &program()).unwrap();
+    vip.run_frame();
+    assert!((0..64).all(|x| (0..32).all(|y| !vip.display.is_set(x, y))));
+}
+
+#[test]
+fn image_sizes() {
+    assert!(Vip::new(&[0; 0x201], &[], &[]).is_err());
+    assert!(Vip::new(&MONITOR, &[0; 0x201], &[]).is_err());
+    assert!(Vip::new(&MONITOR, &[], &[0; 0xE01]).is_err());
+}